@@ -1,4 +1,4 @@
 #!/bin/bash
 
 docker build -t my-geth:0.2 -f docker/geth/Dockerfile .
-docker build -t ya-zksync-prover:0.2.3 -f docker/prover/Dockerfile .
+docker build -t ya-zksync-prover:0.3.0 -f docker/prover/Dockerfile .
@@ -48,4 +48,7 @@ VERIFY_KEYS_TARBAL="verify-keys-`basename $KEY_DIR`-account-"$ACCOUNT_TREE_DEPTH
 [ -f keys/packed/$VERIFY_KEYS_TARBAL ] || (echo Keys file $VERIFY_KEYS_TARBAL not found && exit 1)
 tar xf keys/packed/$VERIFY_KEYS_TARBAL
 
+# yagna-prover compares keys against these digests in check-keys mode (KEY_DIGESTS).
+sha256sum keys/setup/*.key $KEY_DIR/*/verification_block_*.key > keys/digests.sha256
+
 echo key download complete
@@ -0,0 +1,51 @@
+use hyper::HeaderMap;
+use prover_common::auth::{hmac_signature, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+
+/// Maximal difference between request timestamp and server clock.
+const MAX_CLOCK_SKEW_SECS: i64 = 5 * 60;
+
+/// Authentication required from clients. Must match requestor `--server-auth-token`
+/// or `--server-hmac-secret` options.
+#[derive(Clone, Debug)]
+pub enum MockAuth {
+    Bearer(String),
+    Hmac(Vec<u8>),
+}
+
+impl MockAuth {
+    pub fn check(&self, headers: &HeaderMap, path: &str, body: &[u8]) -> Result<(), String> {
+        match self {
+            MockAuth::Bearer(token) => {
+                let expected = format!("Bearer {}", token);
+                match header(headers, "Authorization") {
+                    Some(value) if value == expected => Ok(()),
+                    Some(_) => Err("invalid bearer token".to_string()),
+                    None => Err("missing bearer token".to_string()),
+                }
+            }
+            MockAuth::Hmac(secret) => {
+                let timestamp = header(headers, TIMESTAMP_HEADER)
+                    .ok_or_else(|| "missing request timestamp".to_string())?;
+                let signature = header(headers, SIGNATURE_HEADER)
+                    .ok_or_else(|| "missing request signature".to_string())?;
+
+                let sent_at = timestamp
+                    .parse::<i64>()
+                    .map_err(|_| "invalid request timestamp".to_string())?;
+                if (chrono::Utc::now().timestamp() - sent_at).abs() > MAX_CLOCK_SKEW_SECS {
+                    return Err("request timestamp out of range".to_string());
+                }
+
+                if signature.eq_ignore_ascii_case(&hmac_signature(secret, timestamp, path, body)) {
+                    Ok(())
+                } else {
+                    Err("invalid request signature".to_string())
+                }
+            }
+        }
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
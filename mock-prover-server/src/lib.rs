@@ -0,0 +1,231 @@
+//! Local stand-in for zksync prover server, that lets test `ZksyncClient`
+//! and requestor pipeline without zksync dev environment.
+mod auth;
+mod service;
+mod state;
+
+pub use crate::auth::MockAuth;
+pub use crate::state::{Endpoint, Fault, Job, PublishedProof};
+
+use anyhow::anyhow;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::Server;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::fs::File;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use tokio::sync::oneshot;
+
+use zksync_circuit::account::AccountWitness;
+use zksync_crypto::ff::Field;
+use zksync_crypto::Fr;
+use zksync_prover_utils::prover_data::ProverData;
+
+use crate::state::State;
+
+/// Mock server runs on separate thread with it's own runtime, so it can be used
+/// from both synchronous and asynchronous tests. Server is stopped on drop.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Job description in format saved by requestor in `blocks/job-info-<job>.json`.
+#[derive(Deserialize)]
+struct JobInfo {
+    block_id: i64,
+    block_size: usize,
+}
+
+impl MockServer {
+    /// Starts server on random local port.
+    pub fn start(auth: Option<MockAuth>) -> anyhow::Result<MockServer> {
+        MockServer::bind(([127, 0, 0, 1], 0).into(), auth)
+    }
+
+    pub fn bind(addr: SocketAddr, auth: Option<MockAuth>) -> anyhow::Result<MockServer> {
+        let state = Arc::new(Mutex::new(State::default()));
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
+        let (addr_sender, addr_receiver) = mpsc::channel();
+
+        let server_state = state.clone();
+        let thread = std::thread::spawn(move || {
+            let mut runtime = match tokio::runtime::Builder::new()
+                .basic_scheduler()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    addr_sender.send(Err(anyhow!("{}", e))).ok();
+                    return;
+                }
+            };
+
+            runtime.block_on(async move {
+                let make_service = make_service_fn(move |_| {
+                    let state = server_state.clone();
+                    let auth = auth.clone();
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |request| {
+                            service::handle(state.clone(), auth.clone(), request)
+                        }))
+                    }
+                });
+
+                let server = match Server::try_bind(&addr) {
+                    Ok(builder) => builder.serve(make_service),
+                    Err(e) => {
+                        addr_sender.send(Err(anyhow!("{}", e))).ok();
+                        return;
+                    }
+                };
+                addr_sender.send(Ok(server.local_addr())).ok();
+
+                server
+                    .with_graceful_shutdown(async {
+                        shutdown_receiver.await.ok();
+                    })
+                    .await
+                    .map_err(|e| log::error!("Mock server error: {}", e))
+                    .ok();
+            });
+        });
+
+        let addr = addr_receiver
+            .recv()
+            .map_err(|_| anyhow!("Mock server thread exited before binding"))?
+            .map_err(|e| anyhow!("Failed to start mock server on {}. Error: {}", addr, e))?;
+
+        log::info!("Mock zksync prover server listening on {}", addr);
+        Ok(MockServer {
+            addr,
+            state,
+            shutdown: Some(shutdown_sender),
+            thread: Some(thread),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Base url to pass as requestor `--server-api-url`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Adds block waiting for proof. Returns job id, that will be
+    /// assigned to prover asking for block of this size.
+    pub fn add_block(&self, block_id: i64, block_size: usize, data: ProverData) -> i32 {
+        self.state
+            .lock()
+            .unwrap()
+            .add_job(block_id, block_size, data)
+    }
+
+    /// Loads fixtures from directory with the same layout, as requestor
+    /// `blocks` debug directory: `job-info-<job>.json` and `block-<block>.json` files.
+    pub fn load_fixtures(&self, dir: &Path) -> anyhow::Result<usize> {
+        let mut loaded = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) if name.starts_with("job-info-") && name.ends_with(".json") => name,
+                _ => continue,
+            };
+
+            let info: JobInfo = serde_json::from_reader(File::open(&path)?)
+                .map_err(|e| anyhow!("Failed to parse job info [{}]. Error: {}", name, e))?;
+            let block_path = dir.join(format!("block-{}.json", info.block_id));
+            let data: ProverData =
+                serde_json::from_reader(File::open(&block_path)?).map_err(|e| {
+                    anyhow!(
+                        "Failed to parse block [{}]. Error: {}",
+                        block_path.display(),
+                        e
+                    )
+                })?;
+
+            self.add_block(info.block_id, info.block_size, data);
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+
+    /// Injects fault into next `times` requests to endpoint.
+    pub fn inject(&self, endpoint: Endpoint, fault: Fault, times: usize) {
+        let mut state = self.state.lock().unwrap();
+        let faults = state.faults.entry(endpoint).or_default();
+        for _ in 0..times {
+            faults.push_back(fault.clone());
+        }
+    }
+
+    pub fn published(&self) -> Vec<PublishedProof> {
+        self.state.lock().unwrap().published.clone()
+    }
+
+    pub fn requests(&self, endpoint: Endpoint) -> usize {
+        let state = self.state.lock().unwrap();
+        state.requests.get(&endpoint).cloned().unwrap_or(0)
+    }
+
+    pub fn heartbeats(&self, job_id: i32) -> usize {
+        let state = self.state.lock().unwrap();
+        state.heartbeats.get(&job_id).cloned().unwrap_or(0)
+    }
+
+    pub fn registered_provers(&self) -> Vec<(i32, String)> {
+        let state = self.state.lock().unwrap();
+        state
+            .registered
+            .iter()
+            .map(|(id, name)| (*id, name.clone()))
+            .collect()
+    }
+
+    pub fn is_stopped(&self, prover_id: i32) -> bool {
+        self.state.lock().unwrap().stopped.contains(&prover_id)
+    }
+
+    /// Jobs, that weren't assigned to any prover yet.
+    pub fn pending_jobs(&self) -> Vec<Job> {
+        self.state.lock().unwrap().queue.iter().cloned().collect()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+/// Data of block without operations. Good enough for tests, that don't run real prover.
+pub fn empty_prover_data() -> ProverData {
+    ProverData {
+        public_data_commitment: Fr::zero(),
+        old_root: Fr::zero(),
+        initial_used_subtree_root: Fr::zero(),
+        new_root: Fr::zero(),
+        validator_address: Fr::zero(),
+        operations: vec![],
+        validator_balances: vec![],
+        validator_audit_path: vec![],
+        validator_account: AccountWitness {
+            nonce: None,
+            pub_key_hash: None,
+            address: None,
+        },
+    }
+}
@@ -0,0 +1,51 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+use mock_prover_server::{MockAuth, MockServer};
+
+#[derive(StructOpt)]
+struct Args {
+    #[structopt(long, env, default_value = "127.0.0.1:8088")]
+    listen: SocketAddr,
+    /// Directory with `job-info-<job>.json` and `block-<block>.json` files
+    /// saved by requestor in it's `blocks` directory.
+    #[structopt(long, env, parse(from_os_str))]
+    fixtures: Option<PathBuf>,
+    #[structopt(long, env, conflicts_with = "server-hmac-secret")]
+    server_auth_token: Option<String>,
+    #[structopt(long, env)]
+    server_hmac_secret: Option<String>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::from_args();
+    if std::env::var("RUST_LOG").is_err() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    env_logger::init();
+
+    let auth = match (args.server_auth_token, args.server_hmac_secret) {
+        (Some(token), _) => Some(MockAuth::Bearer(token)),
+        (None, Some(secret)) => Some(MockAuth::Hmac(secret.into_bytes())),
+        (None, None) => None,
+    };
+
+    let server = MockServer::bind(args.listen, auth)?;
+    if let Some(dir) = &args.fixtures {
+        let loaded = server.load_fixtures(dir)?;
+        log::info!("Loaded {} blocks from [{}].", loaded, dir.display());
+    }
+
+    let mut runtime = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()?;
+    runtime.block_on(tokio::signal::ctrl_c())?;
+
+    log::info!(
+        "Stopping mock server. Published {} proofs.",
+        server.published().len()
+    );
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use hyper::{Body, Request, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use zksync_prover_utils::api::{BlockToProveRes, ProverReq, PublishReq, WorkingOnReq};
+
+use crate::auth::MockAuth;
+use crate::state::{Endpoint, Fault, PublishedProof, State};
+
+pub async fn handle(
+    state: Arc<Mutex<State>>,
+    auth: Option<MockAuth>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let path = request.uri().path().to_string();
+    let endpoint = match Endpoint::from_path(&path) {
+        Some(endpoint) => endpoint,
+        None => return Ok(respond(StatusCode::NOT_FOUND, "not found")),
+    };
+
+    let (parts, body) = request.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => return Ok(respond(StatusCode::BAD_REQUEST, &e.to_string())),
+    };
+
+    if let Some(auth) = &auth {
+        if let Err(e) = auth.check(&parts.headers, &path, &body) {
+            log::warn!("Rejecting unauthorized {} request: {}", path, e);
+            return Ok(respond(StatusCode::UNAUTHORIZED, &e));
+        }
+    }
+
+    let fault = {
+        let mut state = state.lock().unwrap();
+        *state.requests.entry(endpoint).or_insert(0) += 1;
+        state.next_fault(endpoint)
+    };
+
+    let fault = match fault {
+        Some(Fault::Delay(delay)) => {
+            tokio::time::delay_for(delay).await;
+            None
+        }
+        fault => fault,
+    };
+
+    if let Some(Fault::Status(code, message)) = &fault {
+        let status = StatusCode::from_u16(*code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        return Ok(respond(status, message));
+    }
+
+    let mut state = state.lock().unwrap();
+    let response = match endpoint {
+        Endpoint::Register => parse::<ProverReq>(&body).map(|req| {
+            state.next_prover_id += 1;
+            let id = state.next_prover_id;
+            log::info!("Registered prover '{}' under id [{}].", req.name, id);
+            state.registered.insert(id, req.name);
+            respond(StatusCode::OK, &id.to_string())
+        }),
+        Endpoint::BlockToProve => parse::<ProverReq>(&body).map(|req| {
+            let res = match state.take_job(req.block_size) {
+                Some(job) => BlockToProveRes {
+                    prover_run_id: job.job_id,
+                    block: job.block_id,
+                },
+                None => BlockToProveRes {
+                    prover_run_id: 0,
+                    block: 0,
+                },
+            };
+            respond_json(&res)
+        }),
+        Endpoint::WorkingOn => parse::<WorkingOnReq>(&body).map(|req| {
+            *state.heartbeats.entry(req.prover_run_id).or_insert(0) += 1;
+            respond(StatusCode::OK, "")
+        }),
+        Endpoint::ProverData => parse::<i64>(&body).map(|block| match fault {
+            Some(Fault::NotReady) => respond_json(&Option::<()>::None),
+            _ => respond_json(&state.data.get(&block)),
+        }),
+        Endpoint::Publish => parse::<PublishReq>(&body).map(|req| {
+            let block_id = req.block as i64;
+            match fault {
+                Some(Fault::DuplicateKey) => {
+                    respond(StatusCode::INTERNAL_SERVER_ERROR, "duplicate key")
+                }
+                _ if state.is_published(block_id) => {
+                    respond(StatusCode::INTERNAL_SERVER_ERROR, "duplicate key")
+                }
+                _ => {
+                    log::info!("Proof for block {} published.", block_id);
+                    state.published.push(PublishedProof {
+                        block_id,
+                        proof: req.proof,
+                    });
+                    respond(StatusCode::OK, "")
+                }
+            }
+        }),
+        Endpoint::Stopped => parse::<i32>(&body).map(|prover_id| {
+            state.stopped.insert(prover_id);
+            respond(StatusCode::OK, "")
+        }),
+    };
+
+    Ok(response.unwrap_or_else(|e| respond(StatusCode::BAD_REQUEST, &e)))
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, String> {
+    serde_json::from_slice(body).map_err(|e| format!("failed to parse request: {}", e))
+}
+
+fn respond(status: StatusCode, body: &str) -> Response<Body> {
+    let mut response = Response::new(Body::from(body.to_string()));
+    *response.status_mut() = status;
+    response
+}
+
+fn respond_json<T: Serialize>(data: &T) -> Response<Body> {
+    match serde_json::to_string(data) {
+        Ok(json) => respond(StatusCode::OK, &json),
+        Err(e) => respond(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+use zksync_crypto::proof::EncodedProofPlonk;
+use zksync_prover_utils::prover_data::ProverData;
+
+/// Endpoints of zksync prover server, that are simulated by `MockServer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    Register,
+    BlockToProve,
+    WorkingOn,
+    ProverData,
+    Publish,
+    Stopped,
+}
+
+impl Endpoint {
+    pub fn from_path(path: &str) -> Option<Endpoint> {
+        Some(match path {
+            "/register" => Endpoint::Register,
+            "/block_to_prove" => Endpoint::BlockToProve,
+            "/working_on" => Endpoint::WorkingOn,
+            "/prover_data" => Endpoint::ProverData,
+            "/publish" => Endpoint::Publish,
+            "/stopped" => Endpoint::Stopped,
+            _ => return None,
+        })
+    }
+}
+
+/// Misbehavior injected into server responses. Each injected fault
+/// is consumed by single request to its endpoint.
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// Delay response. Request is handled normally afterwards.
+    Delay(Duration),
+    /// Respond with given status code and body without handling request.
+    Status(u16, String),
+    /// Respond to `/publish` as if proof was already stored.
+    DuplicateKey,
+    /// Respond to `/prover_data` with no data.
+    NotReady,
+}
+
+#[derive(Clone)]
+pub struct Job {
+    pub block_id: i64,
+    pub job_id: i32,
+    pub block_size: usize,
+    pub data: ProverData,
+}
+
+#[derive(Clone)]
+pub struct PublishedProof {
+    pub block_id: i64,
+    pub proof: EncodedProofPlonk,
+}
+
+#[derive(Default)]
+pub struct State {
+    pub next_prover_id: i32,
+    pub next_job_id: i32,
+    pub registered: HashMap<i32, String>,
+    pub stopped: HashSet<i32>,
+    /// Jobs not yet assigned to any prover.
+    pub queue: VecDeque<Job>,
+    /// Jobs assigned to provers, by job id.
+    pub assigned: HashMap<i32, Job>,
+    /// Heartbeats from `/working_on` by job id.
+    pub heartbeats: HashMap<i32, usize>,
+    pub data: HashMap<i64, ProverData>,
+    pub published: Vec<PublishedProof>,
+    pub faults: HashMap<Endpoint, VecDeque<Fault>>,
+    pub requests: HashMap<Endpoint, usize>,
+}
+
+impl State {
+    pub fn add_job(&mut self, block_id: i64, block_size: usize, data: ProverData) -> i32 {
+        self.next_job_id += 1;
+        let job_id = self.next_job_id;
+
+        self.data.insert(block_id, data.clone());
+        self.queue.push_back(Job {
+            block_id,
+            job_id,
+            block_size,
+            data,
+        });
+        job_id
+    }
+
+    pub fn take_job(&mut self, block_size: usize) -> Option<Job> {
+        let idx = self
+            .queue
+            .iter()
+            .position(|job| job.block_size == block_size)?;
+        let job = self.queue.remove(idx)?;
+        self.assigned.insert(job.job_id, job.clone());
+        Some(job)
+    }
+
+    pub fn next_fault(&mut self, endpoint: Endpoint) -> Option<Fault> {
+        self.faults.get_mut(&endpoint)?.pop_front()
+    }
+
+    pub fn is_published(&self, block_id: i64) -> bool {
+        self.published
+            .iter()
+            .any(|published| published.block_id == block_id)
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::anyhow;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Suffix of files, that are still being written.
+pub const TEMP_SUFFIX: &str = ".tmp";
+
+pub fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_else(OsString::new);
+    name.push(TEMP_SUFFIX);
+    path.with_file_name(name)
+}
+
+/// Writes json to temporary file, fsyncs it and renames into place, so readers
+/// never see truncated artifact.
+pub fn write_json_atomic<T: Serialize>(path: &Path, data: &T) -> anyhow::Result<()> {
+    let temp = temp_path(path);
+    let write = || -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        serde_json::to_writer(&mut writer, data)?;
+        writer.flush()?;
+        writer
+            .into_inner()
+            .map_err(|e| anyhow!("{}", e))?
+            .sync_all()?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    };
+
+    write().map_err(|e| {
+        fs::remove_file(&temp).ok();
+        anyhow!("Failed to write [{}]. Error: {}", path.display(), e)
+    })?;
+
+    // Make rename durable. Not all platforms allow opening directories.
+    if let Some(dir) = path.parent() {
+        File::open(dir).and_then(|dir| dir.sync_all()).ok();
+    }
+    Ok(())
+}
+
+/// Reads json artifact. Returns `None`, if file doesn't exist yet, is still
+/// being written or is incomplete.
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> anyhow::Result<Option<T>> {
+    if temp_path(path).exists() {
+        return Ok(None);
+    }
+
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(anyhow!("Can't open [{}]. Error: {}", path.display(), e)),
+    };
+
+    match serde_json::from_reader(file) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.is_eof() => Ok(None),
+        Err(e) => Err(anyhow!(
+            "Failed to deserialize [{}]. Error: {}",
+            path.display(),
+            e
+        )),
+    }
+}
@@ -0,0 +1,67 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const AUTHORIZATION_HEADER: &str = "Authorization";
+pub const TIMESTAMP_HEADER: &str = "X-Prover-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Prover-Signature";
+
+/// Authentication of requests sent to zksync prover server.
+#[derive(Clone)]
+pub enum Auth {
+    None,
+    Bearer(String),
+    Hmac(Vec<u8>),
+}
+
+impl Auth {
+    /// Bearer token takes precedence over HMAC secret.
+    pub fn new(auth_token: Option<String>, hmac_secret: Option<String>) -> Auth {
+        match (auth_token, hmac_secret) {
+            (Some(token), _) => Auth::Bearer(token),
+            (None, Some(secret)) => Auth::Hmac(secret.into_bytes()),
+            (None, None) => Auth::None,
+        }
+    }
+
+    /// Returns headers authenticating request. `body` must be exactly
+    /// the same bytes, that will be sent to server.
+    pub fn headers(&self, path: &str, body: &[u8]) -> Vec<(&'static str, String)> {
+        match self {
+            Auth::None => vec![],
+            Auth::Bearer(token) => vec![(AUTHORIZATION_HEADER, format!("Bearer {}", token))],
+            Auth::Hmac(secret) => {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|time| time.as_secs())
+                    .unwrap_or_default()
+                    .to_string();
+                let signature = hmac_signature(secret, &timestamp, path, body);
+                vec![(TIMESTAMP_HEADER, timestamp), (SIGNATURE_HEADER, signature)]
+            }
+        }
+    }
+}
+
+// Don't leak secrets to logs.
+impl std::fmt::Debug for Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Auth::None => write!(f, "None"),
+            Auth::Bearer(_) => write!(f, "Bearer(***)"),
+            Auth::Hmac(_) => write!(f, "Hmac(***)"),
+        }
+    }
+}
+
+/// Signature is computed over `<timestamp>.<path>.<body>`. Server should reject
+/// requests with timestamps too far from its own clock to prevent replaying them.
+pub fn hmac_signature(secret: &[u8], timestamp: &str, path: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret).expect("HMAC can take key of any size");
+    mac.input(timestamp.as_bytes());
+    mac.input(b".");
+    mac.input(path.as_bytes());
+    mac.input(b".");
+    mac.input(body);
+    hex::encode(mac.result().code())
+}
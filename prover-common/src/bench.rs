@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::ResultManifest;
+
+/// Benchmark report is placed in proofs directory under this name.
+pub const BENCH_REPORT_FILE: &str = "bench.json";
+
+/// Summary of all runs for single block size.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SizeSummary {
+    pub block_size: usize,
+    pub runs: usize,
+    pub failed: usize,
+    pub min_prove_ms: Option<u64>,
+    pub mean_prove_ms: Option<u64>,
+    pub max_prove_ms: Option<u64>,
+    pub mean_total_ms: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub prover_version: String,
+    pub sizes: Vec<SizeSummary>,
+    /// Samples, that weren't proved, because keys for their size are missing.
+    pub skipped_blocks: Vec<i64>,
+    pub runs: Vec<ResultManifest>,
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+/// Capability report is placed in proofs directory under this name.
+pub const CAPABILITIES_FILE: &str = "capabilities.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyStatus {
+    Ok,
+    Missing,
+    Unreadable,
+    DigestMismatch,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyFile {
+    pub path: String,
+    pub status: KeyStatus,
+    pub sha256: Option<String>,
+    /// Digest from `KEY_DIGESTS` file, if it was listed there.
+    pub expected_sha256: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SizeCapability {
+    pub block_size: usize,
+    pub setup_power: Option<u32>,
+    pub verification_key: KeyFile,
+    pub setup: Option<KeyFile>,
+    pub ready: bool,
+}
+
+/// Block sizes, that yagna-prover is able to prove.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CapabilityReport {
+    pub prover_version: String,
+    pub key_dir: Option<String>,
+    pub sizes: Vec<SizeCapability>,
+    pub ready_sizes: Vec<usize>,
+}
+
+impl CapabilityReport {
+    pub fn all_ready(&self) -> bool {
+        self.sizes.iter().all(|size| size.ready)
+    }
+}
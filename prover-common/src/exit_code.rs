@@ -0,0 +1,2 @@
+/// Exit code of yagna-prover, when computed proof didn't pass verification.
+pub const INVALID_PROOF_EXIT_CODE: i32 = 3;
@@ -0,0 +1,10 @@
+//! Code shared by Requestor and `yagna-prover`, that must behave the same
+//! on both sides.
+pub mod artifact;
+pub mod auth;
+pub mod bench;
+pub mod capabilities;
+pub mod exit_code;
+pub mod manifest;
+pub mod network;
+pub mod retry;
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobStatus {
+    Success,
+    Failed,
+    InvalidProof,
+}
+
+/// Duration of each proving phase in milliseconds. Phases, that weren't
+/// reached are left empty.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PhaseTimings {
+    pub load_ms: Option<u64>,
+    pub prove_ms: Option<u64>,
+    pub verify_ms: Option<u64>,
+    pub write_ms: Option<u64>,
+    pub total_ms: Option<u64>,
+}
+
+/// Outcome of single job, written by yagna-prover to `result-<block>.json`
+/// for every attempted job.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResultManifest {
+    pub block_id: i64,
+    pub job_id: i32,
+    pub block_size: usize,
+    pub status: JobStatus,
+    pub error: Option<String>,
+    pub error_class: Option<String>,
+    pub timings: PhaseTimings,
+    pub peak_memory_kib: Option<u64>,
+    pub prover_version: String,
+    pub key_dir: Option<String>,
+}
+
+impl ResultManifest {
+    pub fn describe_error(&self) -> String {
+        format!(
+            "[{}] {}",
+            self.error_class.as_deref().unwrap_or("unknown"),
+            self.error.as_deref().unwrap_or("no error message")
+        )
+    }
+}
+
+pub fn manifest_path(proofs_dir: &Path, block: i64) -> PathBuf {
+    proofs_dir.join(format!("result-{}.json", block))
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Requestor uploads network config under this name to blocks directory.
+pub const NETWORK_CONFIG_FILE: &str = "network-config.json";
+
+/// Connection to zksync server configured on Requestor and passed to prover
+/// running in network mode, so both talk to server the same way.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Default retry policy in `RetryPolicy` string format.
+    pub retry_policy: Option<String>,
+    /// Retry policies of single endpoints by endpoint name, like `block_to_prove`.
+    pub endpoint_retry_policies: BTreeMap<String, String>,
+    pub auth_token: Option<String>,
+    pub hmac_secret: Option<String>,
+    /// PEM encoded CA certificates to trust.
+    pub ca_bundle: Option<String>,
+    /// Hex encoded PKCS#12 client identity for mutual TLS.
+    pub client_identity: Option<String>,
+    #[serde(default)]
+    pub client_identity_password: String,
+    pub proxy: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Splits PEM bundle into single certificates, because TLS libraries
+/// usually parse only the first one.
+pub fn split_pem_bundle(bundle: &str) -> Vec<String> {
+    const END_MARKER: &str = "-----END CERTIFICATE-----";
+
+    bundle
+        .split_terminator(END_MARKER)
+        .filter(|pem| pem.contains("-----BEGIN CERTIFICATE-----"))
+        .map(|pem| format!("{}{}", pem, END_MARKER))
+        .collect()
+}
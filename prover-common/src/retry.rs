@@ -0,0 +1,355 @@
+use anyhow::{anyhow, bail};
+use backoff::backoff::Backoff;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Exponential backoff parameters for retrying requests to zksync server.
+///
+/// Policy can be parsed from string in format:
+/// `initial=1,multiplier=1.5,max-interval=10,max-elapsed=120,retries=5`,
+/// where all durations are in seconds. Omitted keys take default values.
+/// `max-elapsed=inf` retries without time limit and `none` disables retrying.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub initial_interval: Duration,
+    pub multiplier: f64,
+    pub max_interval: Duration,
+    pub max_elapsed_time: Option<Duration>,
+    pub max_retries: Option<usize>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            initial_interval: Duration::from_secs(1),
+            multiplier: 1.5f64,
+            max_interval: Duration::from_secs(10),
+            max_elapsed_time: Some(Duration::from_secs(2 * 60)),
+            max_retries: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn no_retry() -> Self {
+        RetryPolicy {
+            max_retries: Some(0),
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Starts counting retries of single operation.
+    pub fn start(&self) -> Retries {
+        let mut backoff = backoff::ExponentialBackoff::default();
+        backoff.current_interval = self.initial_interval;
+        backoff.initial_interval = self.initial_interval;
+        backoff.multiplier = self.multiplier;
+        backoff.max_interval = self.max_interval;
+        backoff.max_elapsed_time = self.max_elapsed_time;
+        backoff.reset();
+
+        Retries {
+            backoff,
+            max_retries: self.max_retries,
+            retries: 0,
+        }
+    }
+}
+
+/// Retries of single operation according to `RetryPolicy`.
+pub struct Retries {
+    backoff: backoff::ExponentialBackoff,
+    max_retries: Option<usize>,
+    retries: usize,
+}
+
+impl Retries {
+    /// Returns time to wait before next attempt or `None`, if operation
+    /// shouldn't be retried anymore.
+    pub fn next_wait(&mut self) -> Option<Duration> {
+        match self.max_retries {
+            Some(max_retries) if self.retries >= max_retries => None,
+            _ => {
+                let wait = self.backoff.next_backoff()?;
+                self.retries += 1;
+                Some(wait)
+            }
+        }
+    }
+
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+
+    /// Logs failed attempt of `what` and returns time to wait before retrying it
+    /// or `None`, if policy gives up.
+    pub fn after_failure(&mut self, what: &str, error: &dyn fmt::Display) -> Option<Duration> {
+        match self.next_wait() {
+            Some(wait) => {
+                log::warn!(
+                    "{} failed. Error: <{}>, retrying after: {:.1}s",
+                    what,
+                    error,
+                    wait.as_millis() as f32 / 1000.0f32,
+                );
+                Some(wait)
+            }
+            None => {
+                log::warn!("{} failed. Giving up after {} retries.", what, self.retries);
+                None
+            }
+        }
+    }
+}
+
+/// Server errors (5xx) can be temporary. Client errors (4xx) will repeat on every attempt.
+pub fn is_retryable_status(status: u16) -> bool {
+    (500..600).contains(&status)
+}
+
+/// Server responds with this message, when proof for block was already published.
+/// Publishing it again won't succeed, despite of server error status.
+pub const DUPLICATE_PROOF_RESPONSE: &str = "duplicate key";
+
+/// Blocking version of retry loop. Runs `operation` until it succeeds, returns
+/// `backoff::Error::Permanent` or the policy gives up. The last error is returned
+/// in both failure cases.
+pub fn retry_blocking<T, E, F>(policy: &RetryPolicy, what: &str, mut operation: F) -> Result<T, E>
+where
+    E: fmt::Display,
+    F: FnMut() -> Result<T, backoff::Error<E>>,
+{
+    let mut retries = policy.start();
+    loop {
+        let error = match operation() {
+            Ok(result) => return Ok(result),
+            Err(backoff::Error::Permanent(e)) => return Err(e),
+            Err(backoff::Error::Transient(e)) => e,
+        };
+
+        match retries.after_failure(what, &error) {
+            Some(wait) => std::thread::sleep(wait),
+            None => return Err(error),
+        }
+    }
+}
+
+impl FromStr for RetryPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "none" {
+            return Ok(RetryPolicy::no_retry());
+        }
+
+        let mut policy = RetryPolicy::default();
+        for entry in s
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let mut split = entry.splitn(2, '=');
+            let key = split.next().unwrap_or_default().trim();
+            let value = split
+                .next()
+                .ok_or_else(|| anyhow!("Missing value for retry policy key '{}'", key))?
+                .trim();
+
+            match key {
+                "initial" => policy.initial_interval = parse_secs(value)?,
+                "multiplier" => policy.multiplier = parse_multiplier(value)?,
+                "max-interval" => policy.max_interval = parse_secs(value)?,
+                "max-elapsed" => {
+                    policy.max_elapsed_time = match value {
+                        "inf" => None,
+                        value => Some(parse_secs(value)?),
+                    }
+                }
+                "retries" => policy.max_retries = Some(usize::from_str(value)?),
+                _ => bail!("Unknown retry policy key '{}'", key),
+            }
+        }
+        if policy.initial_interval > policy.max_interval {
+            bail!(
+                "Initial retry interval {}s exceeds max interval {}s",
+                policy.initial_interval.as_secs_f64(),
+                policy.max_interval.as_secs_f64()
+            );
+        }
+        Ok(policy)
+    }
+}
+
+/// Formats policy in the same format, as it is parsed from.
+impl fmt::Display for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "initial={},multiplier={},max-interval={}",
+            self.initial_interval.as_secs_f64(),
+            self.multiplier,
+            self.max_interval.as_secs_f64()
+        )?;
+        match self.max_elapsed_time {
+            Some(max_elapsed) => write!(f, ",max-elapsed={}", max_elapsed.as_secs_f64())?,
+            None => write!(f, ",max-elapsed=inf")?,
+        }
+        if let Some(retries) = self.max_retries {
+            write!(f, ",retries={}", retries)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_multiplier(value: &str) -> anyhow::Result<f64> {
+    let multiplier = f64::from_str(value)
+        .map_err(|e| anyhow!("Invalid retry multiplier '{}'. Error: {}", value, e))?;
+    if !multiplier.is_finite() || multiplier < 1.0 {
+        bail!("Retry multiplier must be a finite number >= 1: '{}'", value);
+    }
+    Ok(multiplier)
+}
+
+fn parse_secs(value: &str) -> anyhow::Result<Duration> {
+    let secs = f64::from_str(value)
+        .map_err(|e| anyhow!("Invalid number of seconds '{}'. Error: {}", value, e))?;
+    if !secs.is_finite() {
+        bail!("Number of seconds must be finite: '{}'", value);
+    }
+    if secs < 0.0 {
+        bail!("Number of seconds can't be negative: '{}'", value);
+    }
+    Ok(Duration::from_secs_f64(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_full_policy() {
+        let policy: RetryPolicy =
+            "initial=0.5,multiplier=2,max-interval=30,max-elapsed=600,retries=5"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            policy,
+            RetryPolicy {
+                initial_interval: Duration::from_millis(500),
+                multiplier: 2.0,
+                max_interval: Duration::from_secs(30),
+                max_elapsed_time: Some(Duration::from_secs(600)),
+                max_retries: Some(5),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_omitted_keys_take_defaults() {
+        let policy: RetryPolicy = "retries=3".parse().unwrap();
+        assert_eq!(
+            policy,
+            RetryPolicy {
+                max_retries: Some(3),
+                ..RetryPolicy::default()
+            }
+        );
+        assert_eq!(RetryPolicy::from_str("").unwrap(), RetryPolicy::default());
+    }
+
+    #[test]
+    fn parse_none_and_inf() {
+        assert_eq!(
+            RetryPolicy::from_str(" none ").unwrap(),
+            RetryPolicy::no_retry()
+        );
+        let policy: RetryPolicy = "max-elapsed=inf".parse().unwrap();
+        assert_eq!(policy.max_elapsed_time, None);
+    }
+
+    #[test]
+    fn parse_invalid_policies() {
+        assert!(RetryPolicy::from_str("timeout=10").is_err());
+        assert!(RetryPolicy::from_str("initial").is_err());
+        assert!(RetryPolicy::from_str("initial=-1").is_err());
+        assert!(RetryPolicy::from_str("initial=abc").is_err());
+        assert!(RetryPolicy::from_str("retries=1.5").is_err());
+    }
+
+    #[test]
+    fn parse_rejects_multiplier_below_one() {
+        assert!(RetryPolicy::from_str("multiplier=0.5").is_err());
+        assert!(RetryPolicy::from_str("multiplier=-2").is_err());
+        assert_eq!(
+            RetryPolicy::from_str("multiplier=1").unwrap().multiplier,
+            1.0
+        );
+    }
+
+    #[test]
+    fn parse_rejects_non_finite_values() {
+        assert!(RetryPolicy::from_str("multiplier=inf").is_err());
+        assert!(RetryPolicy::from_str("multiplier=NaN").is_err());
+        assert!(RetryPolicy::from_str("initial=inf").is_err());
+        assert!(RetryPolicy::from_str("max-interval=NaN").is_err());
+        assert!(RetryPolicy::from_str("max-elapsed=infinity").is_err());
+    }
+
+    #[test]
+    fn parse_rejects_initial_above_max_interval() {
+        assert!(RetryPolicy::from_str("initial=20").is_err());
+        assert!(RetryPolicy::from_str("initial=5,max-interval=2").is_err());
+        let policy: RetryPolicy = "initial=5,max-interval=5".parse().unwrap();
+        assert_eq!(policy.initial_interval, policy.max_interval);
+    }
+
+    #[test]
+    fn only_server_errors_are_retryable() {
+        assert!(is_retryable_status(500));
+        assert!(is_retryable_status(503));
+        assert!(!is_retryable_status(400));
+        assert!(!is_retryable_status(401));
+        assert!(!is_retryable_status(200));
+    }
+
+    #[test]
+    fn blocking_retry_stops_on_permanent_error() {
+        let policy = RetryPolicy {
+            initial_interval: Duration::from_millis(1),
+            max_interval: Duration::from_millis(1),
+            max_retries: Some(5),
+            ..RetryPolicy::default()
+        };
+        let mut attempts = 0;
+        let result: Result<(), _> = retry_blocking(&policy, "test", || {
+            attempts += 1;
+            match attempts {
+                1..=2 => Err(backoff::Error::Transient("temporary")),
+                _ => Err(backoff::Error::Permanent("permanent")),
+            }
+        });
+        assert_eq!(result, Err("permanent"));
+        assert_eq!(attempts, 3);
+
+        attempts = 0;
+        let result: Result<(), _> = retry_blocking(&policy, "test", || {
+            attempts += 1;
+            Err(backoff::Error::Transient("temporary"))
+        });
+        assert_eq!(result, Err("temporary"));
+        assert_eq!(attempts, 6);
+    }
+
+    #[test]
+    fn display_parses_back() {
+        let policies = vec![
+            RetryPolicy::default(),
+            RetryPolicy::no_retry(),
+            "initial=0.25,max-elapsed=inf,retries=7".parse().unwrap(),
+        ];
+        for policy in policies {
+            assert_eq!(policy.to_string().parse::<RetryPolicy>().unwrap(), policy);
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use reqwest::RequestBuilder;
+use structopt::StructOpt;
+
+pub use prover_common::auth::Auth;
+
+/// Authentication of requests sent to zksync prover server.
+/// Without any option set, requests are sent unauthenticated.
+#[derive(StructOpt, Debug, Clone)]
+pub struct AuthArgs {
+    /// Static token sent in `Authorization: Bearer` header.
+    #[structopt(long, env, conflicts_with = "server-hmac-secret")]
+    pub server_auth_token: Option<String>,
+    /// Shared secret used to sign request bodies with HMAC-SHA256.
+    #[structopt(long, env)]
+    pub server_hmac_secret: Option<String>,
+}
+
+impl From<AuthArgs> for Auth {
+    fn from(args: AuthArgs) -> Self {
+        Auth::new(args.server_auth_token, args.server_hmac_secret)
+    }
+}
+
+/// Adds authentication headers to request. `body` must be exactly
+/// the same bytes, that will be sent to server.
+pub fn authorize(auth: &Auth, request: RequestBuilder, path: &str, body: &[u8]) -> RequestBuilder {
+    auth.headers(path, body)
+        .into_iter()
+        .fold(request, |request, (name, value)| {
+            request.header(name, value)
+        })
+}
@@ -0,0 +1,195 @@
+#[cfg(test)]
+pub mod fake;
+pub mod local;
+pub mod yagna;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use futures::stream::{LocalBoxStream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::costs::LinearPricing;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackendKind {
+    Yagna,
+    Local,
+    /// Yagna Provider with local prover as fallback.
+    Auto,
+}
+
+impl FromStr for BackendKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "yagna" => BackendKind::Yagna,
+            "local" => BackendKind::Local,
+            "auto" => BackendKind::Auto,
+            _ => bail!("Unknown backend '{}'. Use: yagna, local or auto.", s),
+        })
+    }
+}
+
+/// Events produced by prover binary while computing proof.
+#[derive(Debug, Clone)]
+pub enum ProverEvent {
+    StdOut(String),
+    StdErr(String),
+    Finished {
+        return_code: i32,
+        message: Option<String>,
+    },
+    /// Backend lost control over prover execution.
+    Failed(String),
+}
+
+pub type ProverEvents = LocalBoxStream<'static, ProverEvent>;
+
+/// Step of job script.
+#[derive(Debug, Clone)]
+pub enum JobCommand {
+    Upload {
+        path: PathBuf,
+        data: serde_json::Value,
+    },
+    Run {
+        args: Vec<String>,
+    },
+    Download {
+        path: PathBuf,
+    },
+}
+
+impl fmt::Display for JobCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobCommand::Upload { path, .. } => write!(f, "upload {}", path.display()),
+            JobCommand::Run { args } => write!(f, "run {}", args.join(" ")),
+            JobCommand::Download { path } => write!(f, "download {}", path.display()),
+        }
+    }
+}
+
+/// Outcome of single step of job script.
+#[derive(Debug, Clone)]
+pub enum CommandResult {
+    Uploaded,
+    /// Prover exit code.
+    Exited(i32),
+    Downloaded(serde_json::Value),
+    Failed(String),
+    /// Not executed, because previous step failed.
+    Skipped,
+}
+
+impl CommandResult {
+    pub fn is_success(&self) -> bool {
+        match self {
+            CommandResult::Uploaded | CommandResult::Downloaded(_) => true,
+            CommandResult::Exited(return_code) => *return_code == 0,
+            CommandResult::Failed(_) | CommandResult::Skipped => false,
+        }
+    }
+}
+
+/// Backend, that passed keys check, with block sizes its prover can prove.
+#[derive(Clone)]
+pub struct ReadyBackend {
+    pub backend: Arc<dyn ProvingBackend>,
+    pub block_sizes: Vec<usize>,
+}
+
+/// Environment, where `yagna-prover` computes proofs. Paths passed to backend
+/// are paths seen by prover, like `/blocks/job-info.json`.
+#[async_trait(?Send)]
+pub trait ProvingBackend {
+    /// Name identifying backend in logs.
+    fn name(&self) -> String;
+    /// Node computing proofs. Used to attribute costs.
+    fn provider(&self) -> String;
+    /// Agreement, that must be terminated after backend is destroyed.
+    fn agreement_id(&self) -> Option<String> {
+        None
+    }
+    /// Subnet, where Provider was found.
+    fn subnet(&self) -> Option<String> {
+        None
+    }
+    /// Pricing from agreement. Backends, that don't charge, have none.
+    fn pricing(&self) -> Option<&LinearPricing> {
+        None
+    }
+    /// Usage counters ordered as in pricing usage vector.
+    async fn usage(&self) -> anyhow::Result<Option<Vec<f64>>> {
+        Ok(None)
+    }
+    async fn upload(&self, path: &Path, data: serde_json::Value) -> anyhow::Result<()>;
+    /// Starts prover. Events stream ends after `Finished` or `Failed` event.
+    async fn run(&self, args: Vec<String>) -> anyhow::Result<ProverEvents>;
+    async fn download(&self, path: &Path) -> anyhow::Result<serde_json::Value>;
+    async fn teardown(&self) -> anyhow::Result<()>;
+
+    /// Executes commands in order and stops at first failed one, like ExeUnit
+    /// does with ExeScript. Backends, that can, send all commands at once.
+    /// Prover output is passed to `output`.
+    async fn run_script(
+        &self,
+        commands: Vec<JobCommand>,
+        output: &mut dyn FnMut(ProverEvent),
+    ) -> anyhow::Result<Vec<CommandResult>> {
+        let mut results: Vec<CommandResult> = vec![];
+        for command in commands {
+            if !results.iter().all(CommandResult::is_success) {
+                results.push(CommandResult::Skipped);
+                continue;
+            }
+
+            let result = match command {
+                JobCommand::Upload { path, data } => self
+                    .upload(&path, data)
+                    .await
+                    .map(|_| CommandResult::Uploaded),
+                JobCommand::Run { args } => match self.run(args).await {
+                    Ok(mut events) => {
+                        let mut result = CommandResult::Failed(
+                            "Prover events stream ended before prover finished.".to_string(),
+                        );
+                        while let Some(event) = events.next().await {
+                            match &event {
+                                ProverEvent::Finished { return_code, .. } => {
+                                    result = CommandResult::Exited(*return_code)
+                                }
+                                ProverEvent::Failed(e) => result = CommandResult::Failed(e.clone()),
+                                _ => (),
+                            }
+                            output(event);
+                        }
+                        Ok(result)
+                    }
+                    Err(e) => Err(e),
+                },
+                JobCommand::Download { path } => {
+                    self.download(&path).await.map(CommandResult::Downloaded)
+                }
+            };
+            results.push(result.unwrap_or_else(|e| CommandResult::Failed(e.to_string())));
+        }
+        Ok(results)
+    }
+}
+
+impl dyn ProvingBackend {
+    pub async fn send_json<T: Serialize>(&self, path: &Path, data: &T) -> anyhow::Result<()> {
+        self.upload(path, serde_json::to_value(data)?).await
+    }
+
+    pub async fn download_json<T: DeserializeOwned>(&self, path: &Path) -> anyhow::Result<T> {
+        Ok(serde_json::from_value(self.download(path).await?)?)
+    }
+}
@@ -0,0 +1,102 @@
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{ProverEvent, ProverEvents, ProvingBackend};
+
+/// Test double keeping files in memory. Instead of running prover, it puts
+/// prepared results to their paths and exits with configured code.
+pub struct FakeBackend {
+    files: RefCell<HashMap<PathBuf, serde_json::Value>>,
+    results: Vec<(PathBuf, serde_json::Value)>,
+    return_code: i32,
+    runs: RefCell<Vec<Vec<String>>>,
+    torn_down: Cell<bool>,
+}
+
+impl FakeBackend {
+    pub fn new(return_code: i32) -> FakeBackend {
+        FakeBackend {
+            files: RefCell::new(HashMap::new()),
+            results: vec![],
+            return_code,
+            runs: RefCell::new(vec![]),
+            torn_down: Cell::new(false),
+        }
+    }
+
+    /// File, that will appear after prover run.
+    pub fn with_result<T: Serialize>(mut self, path: &str, data: &T) -> FakeBackend {
+        let data = serde_json::to_value(data).unwrap();
+        self.results.push((PathBuf::from(path), data));
+        self
+    }
+
+    pub fn file(&self, path: &str) -> Option<serde_json::Value> {
+        self.files.borrow().get(Path::new(path)).cloned()
+    }
+
+    /// Arguments of all prover runs.
+    pub fn runs(&self) -> Vec<Vec<String>> {
+        self.runs.borrow().clone()
+    }
+
+    pub fn torn_down(&self) -> bool {
+        self.torn_down.get()
+    }
+}
+
+#[async_trait(?Send)]
+impl ProvingBackend for FakeBackend {
+    fn name(&self) -> String {
+        "fake backend".to_string()
+    }
+
+    fn provider(&self) -> String {
+        "fake".to_string()
+    }
+
+    async fn upload(&self, path: &Path, data: serde_json::Value) -> anyhow::Result<()> {
+        self.files.borrow_mut().insert(path.to_path_buf(), data);
+        Ok(())
+    }
+
+    async fn run(&self, args: Vec<String>) -> anyhow::Result<ProverEvents> {
+        self.runs.borrow_mut().push(args);
+
+        // Real prover can't do anything without job.
+        let return_code = match self.file("/blocks/job-info.json") {
+            Some(_) => {
+                let mut files = self.files.borrow_mut();
+                files.extend(self.results.iter().cloned());
+                self.return_code
+            }
+            None => 1,
+        };
+
+        let events = vec![
+            ProverEvent::StdOut("proving\n".to_string()),
+            ProverEvent::Finished {
+                return_code,
+                message: None,
+            },
+        ];
+        Ok(stream::iter(events).boxed_local())
+    }
+
+    async fn download(&self, path: &Path) -> anyhow::Result<serde_json::Value> {
+        self.files
+            .borrow()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("File [{}] doesn't exist.", path.display()))
+    }
+
+    async fn teardown(&self) -> anyhow::Result<()> {
+        self.torn_down.set(true);
+        Ok(())
+    }
+}
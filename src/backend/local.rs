@@ -0,0 +1,161 @@
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use futures::channel::{mpsc, oneshot};
+use futures::future::{select, Either, FutureExt};
+use futures::{SinkExt, StreamExt};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex;
+use tempfile::TempDir;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+
+use prover_common::artifact::{read_json, write_json_atomic};
+
+use super::{ProverEvent, ProverEvents, ProvingBackend};
+
+/// Runs `yagna-prover` binary as child process on Requestor machine.
+/// Directories `/blocks` and `/proofs` seen by prover on Provider
+/// are replaced with temporary directories.
+pub struct LocalBackend {
+    binary: PathBuf,
+    workdir: TempDir,
+    /// Kills last started prover. Child is owned by task forwarding its output,
+    /// so dropping events stream doesn't stop it.
+    kill: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+impl LocalBackend {
+    pub fn new(binary: &Path) -> anyhow::Result<LocalBackend> {
+        let workdir = tempfile::Builder::new()
+            .prefix("yagna-prover-")
+            .tempdir()
+            .map_err(|e| anyhow!("Can't create local prover workdir. Error: {}", e))?;
+        fs::create_dir_all(workdir.path().join("blocks"))?;
+        fs::create_dir_all(workdir.path().join("proofs"))?;
+
+        log::info!(
+            "Using local prover [{}] with workdir [{}].",
+            binary.display(),
+            workdir.path().display()
+        );
+        Ok(LocalBackend {
+            binary: binary.to_path_buf(),
+            workdir,
+            kill: Mutex::new(None),
+        })
+    }
+
+    fn blocks_dir(&self) -> PathBuf {
+        self.workdir.path().join("blocks")
+    }
+
+    fn proofs_dir(&self) -> PathBuf {
+        self.workdir.path().join("proofs")
+    }
+
+    /// Translates path seen by prover on Provider to local path.
+    fn local_path(&self, path: &Path) -> anyhow::Result<PathBuf> {
+        if let Ok(relative) = path.strip_prefix("/blocks") {
+            Ok(self.blocks_dir().join(relative))
+        } else if let Ok(relative) = path.strip_prefix("/proofs") {
+            Ok(self.proofs_dir().join(relative))
+        } else {
+            bail!(
+                "Path [{}] is outside of prover directories.",
+                path.display()
+            )
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ProvingBackend for LocalBackend {
+    fn name(&self) -> String {
+        "local prover".to_string()
+    }
+
+    fn provider(&self) -> String {
+        "local".to_string()
+    }
+
+    async fn upload(&self, path: &Path, data: serde_json::Value) -> anyhow::Result<()> {
+        let path = self.local_path(path)?;
+        write_json_atomic(&path, &data)
+    }
+
+    async fn run(&self, args: Vec<String>) -> anyhow::Result<ProverEvents> {
+        let mut child = Command::new(&self.binary)
+            .args(args)
+            .env("BLOCKS_DIR", self.blocks_dir())
+            .env("PROOFS_DIR", self.proofs_dir())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                anyhow!(
+                    "Can't spawn local prover [{}]. Error: {}",
+                    self.binary.display(),
+                    e
+                )
+            })?;
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+
+        // Replaced sender kills previous prover, if it still runs.
+        let (kill, killed) = oneshot::channel();
+        *self.kill.lock().unwrap() = Some(kill);
+
+        let (mut sender, receiver) = mpsc::unbounded();
+        actix_rt::spawn(async move {
+            let output = futures::future::join(
+                forward_lines(stdout, sender.clone(), ProverEvent::StdOut),
+                forward_lines(stderr, sender.clone(), ProverEvent::StdErr),
+            );
+            // Output ends, when prover exits.
+            if let Either::Right(_) = select(output.boxed_local(), killed).await {
+                child.kill().ok();
+            }
+
+            let event = match child.await {
+                Ok(status) => ProverEvent::Finished {
+                    return_code: status.code().unwrap_or(-1),
+                    message: match status.code() {
+                        Some(_) => None,
+                        None => Some("terminated by signal".to_string()),
+                    },
+                },
+                Err(e) => ProverEvent::Failed(e.to_string()),
+            };
+            sender.send(event).await.ok();
+        });
+        Ok(receiver.boxed_local())
+    }
+
+    async fn download(&self, path: &Path) -> anyhow::Result<serde_json::Value> {
+        let path = self.local_path(path)?;
+        read_json(&path)?.ok_or_else(|| anyhow!("File [{}] is not ready yet.", path.display()))
+    }
+
+    async fn teardown(&self) -> anyhow::Result<()> {
+        if let Some(kill) = self.kill.lock().unwrap().take() {
+            kill.send(()).ok();
+        }
+        // Temporary directory will be removed on drop.
+        Ok(())
+    }
+}
+
+async fn forward_lines<R: AsyncRead + Unpin>(
+    output: R,
+    mut sender: mpsc::UnboundedSender<ProverEvent>,
+    event: fn(String) -> ProverEvent,
+) {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        sender.send(event(line + "\n")).await.ok();
+    }
+}
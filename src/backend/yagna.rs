@@ -0,0 +1,311 @@
+use async_trait::async_trait;
+use futures::channel::mpsc;
+use futures::future::ready;
+use futures::{SinkExt, StreamExt};
+use std::path::Path;
+use std::sync::Arc;
+
+use tempfile::NamedTempFile;
+use url::Url;
+use ya_client::activity::ActivityRequestorApi;
+use ya_client_model::activity::{
+    Capture, CaptureMode, CommandOutput, ExeScriptRequest, RuntimeEventKind,
+};
+use yarapi::rest::activity::DefaultActivity;
+use yarapi::rest::streaming::StreamingActivity;
+use yarapi::rest::{self, Activity, Transfers};
+
+use crate::costs::LinearPricing;
+use crate::market::NegotiatedAgreement;
+
+use super::{CommandResult, JobCommand, ProverEvent, ProverEvents, ProvingBackend};
+
+/// Runs prover in ExeUnit on yagna Provider.
+pub struct YagnaBackend {
+    activity: Arc<DefaultActivity>,
+    activity_api: ActivityRequestorApi,
+    agreement_id: String,
+    provider_id: String,
+    subnet: String,
+    pricing: Option<LinearPricing>,
+}
+
+impl YagnaBackend {
+    pub fn new(
+        activity: Arc<DefaultActivity>,
+        activity_api: ActivityRequestorApi,
+        agreement: &NegotiatedAgreement,
+    ) -> YagnaBackend {
+        YagnaBackend {
+            activity,
+            activity_api,
+            agreement_id: agreement.agreement_id.clone(),
+            provider_id: agreement.offer.provider_id.clone(),
+            subnet: agreement.subnet.clone(),
+            pricing: agreement.offer.pricing.clone(),
+        }
+    }
+
+    /// Deploys image and starts ExeUnit.
+    pub async fn deploy(&self) -> anyhow::Result<()> {
+        log::info!("Deploying image and starting ExeUnit...");
+        self.activity
+            .execute_commands(vec![
+                rest::ExeScriptCommand::Deploy {},
+                rest::ExeScriptCommand::Start { args: vec![] },
+            ])
+            .await?;
+        log::info!("Image deployed. ExeUnit started.");
+        Ok(())
+    }
+
+    /// Sends all commands as single ExeScript batch and tracks results
+    /// of commands by their index in batch.
+    async fn run_batch(
+        &self,
+        commands: &[JobCommand],
+        files: &mut Vec<Option<(NamedTempFile, Url)>>,
+        output: &mut dyn FnMut(ProverEvent),
+    ) -> anyhow::Result<Vec<CommandResult>> {
+        let mut script = vec![];
+        for command in commands {
+            let (exe_command, file) = match command {
+                JobCommand::Upload { path, data } => {
+                    let file = NamedTempFile::new()?;
+                    serde_json::to_writer(file.as_file(), data)?;
+                    let url = gftp::publish(file.path()).await?;
+                    let transfer = rest::ExeScriptCommand::Transfer {
+                        from: url.to_string(),
+                        to: format!("container:{}", path.display()),
+                        args: Default::default(),
+                    };
+                    (transfer, Some((file, url)))
+                }
+                JobCommand::Run { args } => {
+                    let stream = || {
+                        Some(CaptureMode::Stream {
+                            limit: None,
+                            format: None,
+                        })
+                    };
+                    let run = rest::ExeScriptCommand::Run {
+                        entry_point: "/bin/yagna-prover".to_string(),
+                        args: args.clone(),
+                        capture: Some(Capture {
+                            stdout: stream(),
+                            stderr: stream(),
+                        }),
+                    };
+                    (run, None)
+                }
+                JobCommand::Download { path } => {
+                    let file = NamedTempFile::new()?;
+                    let url = gftp::open_for_upload(file.path()).await?;
+                    let transfer = rest::ExeScriptCommand::Transfer {
+                        from: format!("container:{}", path.display()),
+                        to: url.to_string(),
+                        args: Default::default(),
+                    };
+                    (transfer, Some((file, url)))
+                }
+            };
+            script.push(exe_command);
+            files.push(file);
+        }
+
+        let control = self.activity_api.control();
+        let batch_id = control
+            .exec(
+                ExeScriptRequest::new(serde_json::to_string(&script)?),
+                self.activity.id(),
+            )
+            .await?;
+        log::debug!(
+            "Started batch [{}] with {} commands on {}.",
+            batch_id,
+            script.len(),
+            self.name()
+        );
+
+        let mut results = vec![CommandResult::Skipped; commands.len()];
+        let mut events = control
+            .stream_exec_batch_results(self.activity.id(), &batch_id)
+            .await?
+            .boxed_local();
+        while let Some(event) = events.next().await {
+            let index = event.index;
+            match event.kind {
+                RuntimeEventKind::StdOut(output_text) => {
+                    output(ProverEvent::StdOut(to_text(output_text)))
+                }
+                RuntimeEventKind::StdErr(output_text) => {
+                    output(ProverEvent::StdErr(to_text(output_text)))
+                }
+                RuntimeEventKind::Finished {
+                    return_code,
+                    message,
+                } => {
+                    let result = match commands.get(index) {
+                        Some(JobCommand::Run { .. }) => {
+                            output(ProverEvent::Finished {
+                                return_code,
+                                message,
+                            });
+                            CommandResult::Exited(return_code)
+                        }
+                        _ if return_code != 0 => CommandResult::Failed(
+                            message.unwrap_or_else(|| format!("exit code {}", return_code)),
+                        ),
+                        Some(JobCommand::Upload { .. }) => CommandResult::Uploaded,
+                        Some(JobCommand::Download { .. }) => {
+                            match files.get(index).and_then(Option::as_ref) {
+                                Some((file, _)) => read_download(file),
+                                None => CommandResult::Failed("no file".to_string()),
+                            }
+                        }
+                        None => continue,
+                    };
+
+                    // ExeUnit doesn't execute commands after failed one.
+                    let last = index + 1 == commands.len() || !result.is_success();
+                    results[index] = result;
+                    if last {
+                        break;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(results)
+    }
+}
+
+#[async_trait(?Send)]
+impl ProvingBackend for YagnaBackend {
+    fn name(&self) -> String {
+        format!("activity [{}]", self.activity.id())
+    }
+
+    fn provider(&self) -> String {
+        self.provider_id.clone()
+    }
+
+    fn agreement_id(&self) -> Option<String> {
+        Some(self.agreement_id.clone())
+    }
+
+    fn subnet(&self) -> Option<String> {
+        Some(self.subnet.clone())
+    }
+
+    fn pricing(&self) -> Option<&LinearPricing> {
+        self.pricing.as_ref()
+    }
+
+    async fn usage(&self) -> anyhow::Result<Option<Vec<f64>>> {
+        let usage = self
+            .activity_api
+            .state()
+            .get_usage(self.activity.id())
+            .await?;
+        Ok(usage.current_usage)
+    }
+
+    async fn upload(&self, path: &Path, data: serde_json::Value) -> anyhow::Result<()> {
+        Ok(self.activity.send_json(path, &data).await?)
+    }
+
+    async fn run(&self, args: Vec<String>) -> anyhow::Result<ProverEvents> {
+        let batch = self
+            .activity
+            .run_streaming("/bin/yagna-prover", args)
+            .await?
+            .debug(".debug")?;
+
+        let (mut sender, receiver) = mpsc::unbounded();
+        actix_rt::spawn(async move {
+            let stream = match batch.stream().await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    sender.send(ProverEvent::Failed(e.to_string())).await.ok();
+                    return;
+                }
+            };
+
+            let mut events = stream
+                .map(|event| match event.kind {
+                    RuntimeEventKind::StdOut(output) => Some(ProverEvent::StdOut(to_text(output))),
+                    RuntimeEventKind::StdErr(output) => Some(ProverEvent::StdErr(to_text(output))),
+                    RuntimeEventKind::Finished {
+                        return_code,
+                        message,
+                    } => Some(ProverEvent::Finished {
+                        return_code,
+                        message,
+                    }),
+                    _ => None,
+                })
+                .filter_map(ready)
+                .boxed_local();
+
+            let mut finished = false;
+            while let Some(event) = events.next().await {
+                finished = matches!(event, ProverEvent::Finished { .. });
+                sender.send(event).await.ok();
+                if finished {
+                    break;
+                }
+            }
+
+            match batch.wait_for_finish().await {
+                Err(e) if !finished => {
+                    sender.send(ProverEvent::Failed(e.to_string())).await.ok();
+                }
+                Err(e) => log::warn!("Failed to wait for batch finish. Error: {}", e),
+                Ok(_) => (),
+            }
+        });
+        Ok(receiver.boxed_local())
+    }
+
+    async fn download(&self, path: &Path) -> anyhow::Result<serde_json::Value> {
+        Ok(self.activity.download_json(path).await?)
+    }
+
+    async fn teardown(&self) -> anyhow::Result<()> {
+        Ok(self.activity.destroy().await?)
+    }
+
+    async fn run_script(
+        &self,
+        commands: Vec<JobCommand>,
+        output: &mut dyn FnMut(ProverEvent),
+    ) -> anyhow::Result<Vec<CommandResult>> {
+        // Files published with gftp must live until batch is finished and
+        // are closed, even if batch fails.
+        let mut files = vec![];
+        let result = self.run_batch(&commands, &mut files, output).await;
+        for (_, url) in files.iter().flatten() {
+            gftp::close(url).await.ok();
+        }
+        result
+    }
+}
+
+fn read_download(file: &NamedTempFile) -> CommandResult {
+    match std::fs::read(file.path())
+        .map_err(|e| e.to_string())
+        .and_then(|content| serde_json::from_slice(&content).map_err(|e| e.to_string()))
+    {
+        Ok(value) => CommandResult::Downloaded(value),
+        Err(e) => CommandResult::Failed(format!("Downloaded file is invalid. {}", e)),
+    }
+}
+
+fn to_text(output: CommandOutput) -> String {
+    match output {
+        CommandOutput::Str(text) => text,
+        CommandOutput::Bin(vec) => String::from_utf8_lossy(&vec).to_string(),
+    }
+}
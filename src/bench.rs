@@ -0,0 +1,125 @@
+use anyhow::anyhow;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+use crate::backend::ProvingBackend;
+use crate::prover_runner::wait_for_prover;
+use prover_common::artifact::write_json_atomic;
+use prover_common::bench::{BenchReport, BENCH_REPORT_FILE};
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct BenchArgs {
+    /// How many times each sample block is proved.
+    #[structopt(long, default_value = "1")]
+    pub repeat: usize,
+    /// Report from previous benchmark to compare with.
+    #[structopt(long, parse(from_os_str))]
+    pub baseline: Option<PathBuf>,
+    /// Print report as json instead of table.
+    #[structopt(long)]
+    pub json: bool,
+}
+
+/// Proves sample blocks bundled with prover image and prints timings.
+pub async fn run_bench(backend: &dyn ProvingBackend, args: &BenchArgs) -> anyhow::Result<()> {
+    let baseline = match &args.baseline {
+        Some(path) => Some(
+            serde_json::from_reader::<_, BenchReport>(File::open(path)?).map_err(|e| {
+                anyhow!(
+                    "Can't read baseline report [{}]. Error: {}",
+                    path.display(),
+                    e
+                )
+            })?,
+        ),
+        None => None,
+    };
+
+    log::info!("Running benchmark on {}...", backend.name());
+    let return_code = wait_for_prover(
+        backend,
+        vec![
+            "ya-prover".to_string(),
+            "--mode".to_string(),
+            "bench".to_string(),
+            "--bench-repeat".to_string(),
+            args.repeat.to_string(),
+        ],
+    )
+    .await?;
+
+    let report: BenchReport = backend
+        .download_json(&Path::new("/proofs").join(BENCH_REPORT_FILE))
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "Benchmark exited with code {} without report. Error: {}",
+                return_code,
+                e
+            )
+        })?;
+
+    write_json_atomic(&PathBuf::from(BENCH_REPORT_FILE), &report)
+        .map_err(|e| log::warn!("Failed to save benchmark report. {}", e))
+        .ok();
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_comparison(&backend.name(), &report, baseline.as_ref());
+    }
+    Ok(())
+}
+
+fn print_comparison(name: &str, report: &BenchReport, baseline: Option<&BenchReport>) {
+    println!("Benchmark of prover {} on {}", report.prover_version, name);
+    println!(
+        "{:>6} {:>5} {:>7} {:>10} {:>10} {:>10} {:>10} {:>9}",
+        "size", "runs", "failed", "min [s]", "mean [s]", "max [s]", "base [s]", "speedup"
+    );
+
+    for size in &report.sizes {
+        let base = baseline
+            .and_then(|baseline| {
+                baseline
+                    .sizes
+                    .iter()
+                    .find(|base| base.block_size == size.block_size)
+            })
+            .and_then(|base| base.mean_prove_ms);
+        let speedup = match (base, size.mean_prove_ms) {
+            (Some(base), Some(mean)) if mean > 0 => format!("{:.2}x", base as f64 / mean as f64),
+            _ => "-".to_string(),
+        };
+
+        println!(
+            "{:>6} {:>5} {:>7} {:>10} {:>10} {:>10} {:>10} {:>9}",
+            size.block_size,
+            size.runs,
+            size.failed,
+            seconds(size.min_prove_ms),
+            seconds(size.mean_prove_ms),
+            seconds(size.max_prove_ms),
+            seconds(base),
+            speedup
+        );
+    }
+
+    if let Some(peak) = report
+        .runs
+        .iter()
+        .filter_map(|run| run.peak_memory_kib)
+        .max()
+    {
+        println!("Peak memory: {} MiB", peak / 1024);
+    }
+    if !report.skipped_blocks.is_empty() {
+        println!("Skipped samples without keys: {:?}", report.skipped_blocks);
+    }
+}
+
+fn seconds(ms: Option<u64>) -> String {
+    ms.map(|ms| format!("{:.1}", ms as f64 / 1000.0))
+        .unwrap_or_else(|| "-".to_string())
+}
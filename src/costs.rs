@@ -0,0 +1,494 @@
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use structopt::StructOpt;
+
+pub const PRICING_COEFFS_PROPERTY: &str = "golem.com.pricing.model.linear.coeffs";
+pub const USAGE_VECTOR_PROPERTY: &str = "golem.com.usage.vector";
+pub const DURATION_COUNTER: &str = "golem.usage.duration_sec";
+pub const CPU_COUNTER: &str = "golem.usage.cpu_sec";
+/// Ledger with costs of all jobs in working directory.
+pub const COSTS_FILE: &str = "costs.jsonl";
+
+/// Linear pricing model from Provider offer. Price is sum of usage counters
+/// multiplied by coefficients and fixed price for starting activity.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LinearPricing {
+    pub usage_vector: Vec<String>,
+    pub coeffs: Vec<f64>,
+    pub fixed: f64,
+}
+
+impl LinearPricing {
+    pub fn from_properties(properties: &serde_json::Value) -> anyhow::Result<LinearPricing> {
+        let coeffs: Vec<f64> = property(properties, PRICING_COEFFS_PROPERTY)?;
+        let usage_vector: Vec<String> = property(properties, USAGE_VECTOR_PROPERTY)?;
+
+        // Last coefficient is fixed price.
+        if coeffs.len() != usage_vector.len() + 1 {
+            bail!(
+                "Pricing has {} coefficients for {} usage counters.",
+                coeffs.len(),
+                usage_vector.len()
+            );
+        }
+        Ok(LinearPricing {
+            fixed: coeffs[usage_vector.len()],
+            coeffs: coeffs[..usage_vector.len()].to_vec(),
+            usage_vector,
+        })
+    }
+
+    /// Cost of usage without fixed price, which is paid once per activity.
+    pub fn cost(&self, usage: &[f64]) -> f64 {
+        self.coeffs
+            .iter()
+            .zip(usage.iter())
+            .map(|(coeff, value)| coeff * value)
+            .sum()
+    }
+
+    /// Price for unit of usage counter.
+    pub fn coeff(&self, name: &str) -> Option<f64> {
+        self.counter(&self.coeffs, name)
+    }
+
+    pub fn counter(&self, usage: &[f64], name: &str) -> Option<f64> {
+        self.usage_vector
+            .iter()
+            .position(|counter| counter == name)
+            .and_then(|idx| usage.get(idx).cloned())
+    }
+}
+
+/// Properties can be either flat with dotted keys or nested objects.
+pub fn property<T: serde::de::DeserializeOwned>(
+    properties: &serde_json::Value,
+    name: &str,
+) -> anyhow::Result<T> {
+    let value = properties
+        .get(name)
+        .or_else(|| properties.pointer(&format!("/{}", name.replace('.', "/"))))
+        .ok_or_else(|| anyhow!("Offer has no property {}.", name))?;
+    serde_json::from_value(value.clone())
+        .map_err(|e| anyhow!("Invalid property {}. Error: {}", name, e))
+}
+
+/// Estimated cost of proving single block, computed from activity usage
+/// counters before and after prover run.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JobCost {
+    pub block_id: i64,
+    pub job_id: i32,
+    pub block_size: usize,
+    pub provider: String,
+    /// Entries written before multiple subnets support have no subnet.
+    #[serde(default)]
+    pub subnet: Option<String>,
+    pub finished_at: DateTime<Utc>,
+    pub return_code: Option<i32>,
+    pub duration_sec: Option<f64>,
+    pub cpu_sec: Option<f64>,
+    pub cost: Option<f64>,
+}
+
+/// Estimates cost from usage counters read before and after prover run.
+pub fn estimate(
+    pricing: Option<&LinearPricing>,
+    before: Option<&[f64]>,
+    after: Option<&[f64]>,
+) -> (Option<f64>, Option<f64>, Option<f64>) {
+    let (pricing, before, after) = match (pricing, before, after) {
+        (Some(pricing), Some(before), Some(after)) => (pricing, before, after),
+        _ => return (None, None, None),
+    };
+
+    let delta = after
+        .iter()
+        .zip(before.iter().chain(std::iter::repeat(&0.0)))
+        .map(|(after, before)| after - before)
+        .collect::<Vec<_>>();
+    (
+        pricing.counter(&delta, DURATION_COUNTER),
+        pricing.counter(&delta, CPU_COUNTER),
+        Some(pricing.cost(&delta)),
+    )
+}
+
+/// Costs of all jobs are appended as json lines to file in working directory.
+pub struct CostLedger {
+    path: PathBuf,
+    /// Running maximum of job costs. Ledger is read only on first use.
+    max_cost: Mutex<Option<f64>>,
+}
+
+impl CostLedger {
+    pub fn new(path: &Path) -> CostLedger {
+        CostLedger {
+            path: path.to_path_buf(),
+            max_cost: Mutex::new(None),
+        }
+    }
+
+    pub fn record(&self, cost: &JobCost) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(cost)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| anyhow!("Can't open [{}]. Error: {}", self.path.display(), e))?;
+        file.write_all(line.as_bytes())?;
+        file.sync_all()?;
+
+        if let (Some(max_cost), Some(cost)) = (self.max_cost.lock().unwrap().as_mut(), cost.cost) {
+            *max_cost = max_cost.max(cost);
+        }
+        Ok(())
+    }
+
+    /// Highest cost of single job recorded so far.
+    pub fn max_cost(&self) -> f64 {
+        let mut max_cost = self.max_cost.lock().unwrap();
+        *max_cost.get_or_insert_with(|| {
+            self.load()
+                .map_err(|e| log::warn!("Can't read cost ledger. {}", e))
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|cost| cost.cost)
+                .fold(0.0, f64::max)
+        })
+    }
+
+    /// Incomplete last line, left by interrupted write, is skipped.
+    pub fn load(&self) -> anyhow::Result<Vec<JobCost>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => bail!("Can't open [{}]. Error: {}", self.path.display(), e),
+        };
+
+        let mut costs = vec![];
+        for line in BufReader::new(file).lines() {
+            match serde_json::from_str(&line?) {
+                Ok(cost) => costs.push(cost),
+                Err(e) if e.is_eof() => continue,
+                Err(e) => log::warn!("Skipping invalid entry in cost ledger. {}", e),
+            }
+        }
+        Ok(costs)
+    }
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct CostsArgs {
+    /// Print report as json instead of table.
+    #[structopt(long)]
+    pub json: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CostSummary {
+    pub jobs: usize,
+    /// Jobs without usage information, for example proved locally.
+    pub unpriced: usize,
+    pub total_cost: f64,
+    pub mean_cost: Option<f64>,
+    pub mean_duration_sec: Option<f64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CostReport {
+    pub by_block_size: BTreeMap<usize, CostSummary>,
+    pub by_provider: BTreeMap<String, CostSummary>,
+    pub by_subnet: BTreeMap<String, CostSummary>,
+}
+
+impl CostReport {
+    pub fn new(costs: &[JobCost]) -> CostReport {
+        let mut by_block_size = BTreeMap::<usize, Vec<&JobCost>>::new();
+        let mut by_provider = BTreeMap::<String, Vec<&JobCost>>::new();
+        let mut by_subnet = BTreeMap::<String, Vec<&JobCost>>::new();
+        for cost in costs {
+            by_block_size.entry(cost.block_size).or_default().push(cost);
+            by_provider
+                .entry(cost.provider.clone())
+                .or_default()
+                .push(cost);
+            by_subnet
+                .entry(cost.subnet.clone().unwrap_or_else(|| "-".to_string()))
+                .or_default()
+                .push(cost);
+        }
+
+        CostReport {
+            by_block_size: by_block_size
+                .into_iter()
+                .map(|(size, costs)| (size, summarize(&costs)))
+                .collect(),
+            by_provider: by_provider
+                .into_iter()
+                .map(|(provider, costs)| (provider, summarize(&costs)))
+                .collect(),
+            by_subnet: by_subnet
+                .into_iter()
+                .map(|(subnet, costs)| (subnet, summarize(&costs)))
+                .collect(),
+        }
+    }
+}
+
+fn summarize(costs: &[&JobCost]) -> CostSummary {
+    let priced = costs
+        .iter()
+        .filter_map(|cost| cost.cost)
+        .collect::<Vec<_>>();
+    let durations = costs
+        .iter()
+        .filter_map(|cost| cost.duration_sec)
+        .collect::<Vec<_>>();
+
+    CostSummary {
+        jobs: costs.len(),
+        unpriced: costs.len() - priced.len(),
+        total_cost: priced.iter().sum(),
+        mean_cost: mean(&priced),
+        mean_duration_sec: mean(&durations),
+    }
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    match values.len() {
+        0 => None,
+        len => Some(values.iter().sum::<f64>() / len as f64),
+    }
+}
+
+pub fn print_costs(ledger: &CostLedger, args: &CostsArgs) -> anyhow::Result<()> {
+    let report = CostReport::new(&ledger.load()?);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("Costs per block size (fixed activity start price not included)");
+    print_table(
+        "size",
+        report
+            .by_block_size
+            .iter()
+            .map(|(size, summary)| (size.to_string(), summary)),
+    );
+    println!();
+    println!("Costs per provider");
+    print_table(
+        "provider",
+        report
+            .by_provider
+            .iter()
+            .map(|(provider, summary)| (provider.clone(), summary)),
+    );
+    println!();
+    println!("Costs per subnet");
+    print_table(
+        "subnet",
+        report
+            .by_subnet
+            .iter()
+            .map(|(subnet, summary)| (subnet.clone(), summary)),
+    );
+    Ok(())
+}
+
+fn print_table<'a>(key: &str, rows: impl Iterator<Item = (String, &'a CostSummary)>) {
+    println!(
+        "{:<44} {:>6} {:>9} {:>12} {:>12} {:>14}",
+        key, "jobs", "unpriced", "total", "mean", "mean time [s]"
+    );
+    for (name, summary) in rows {
+        println!(
+            "{:<44} {:>6} {:>9} {:>12.6} {:>12} {:>14}",
+            name,
+            summary.jobs,
+            summary.unpriced,
+            summary.total_cost,
+            format_value(summary.mean_cost, 6),
+            format_value(summary.mean_duration_sec, 1)
+        );
+    }
+}
+
+pub fn format_value(value: Option<f64>, precision: usize) -> String {
+    value
+        .map(|value| format!("{:.*}", precision, value))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ledger, workdir};
+    use serde_json::json;
+
+    fn pricing() -> LinearPricing {
+        LinearPricing::from_properties(&json!({
+            "golem.com.pricing.model.linear.coeffs": [0.1, 0.2, 1.0],
+            "golem.com.usage.vector": [DURATION_COUNTER, CPU_COUNTER],
+        }))
+        .unwrap()
+    }
+
+    fn job_cost(job_id: i32, block_size: usize, provider: &str, cost: Option<f64>) -> JobCost {
+        JobCost {
+            block_id: job_id as i64,
+            job_id,
+            block_size,
+            provider: provider.to_string(),
+            subnet: None,
+            finished_at: Utc::now(),
+            return_code: Some(0),
+            duration_sec: Some(10.0),
+            cpu_sec: Some(8.0),
+            cost,
+        }
+    }
+
+    #[test]
+    fn pricing_from_flat_and_nested_properties() {
+        let pricing = pricing();
+        assert_eq!(pricing.coeffs, vec![0.1, 0.2]);
+        assert_eq!(pricing.fixed, 1.0);
+        assert_eq!(pricing.coeff(CPU_COUNTER), Some(0.2));
+        assert_eq!(pricing.coeff("golem.usage.gib"), None);
+
+        let nested = LinearPricing::from_properties(&json!({
+            "golem": {
+                "com": {
+                    "pricing": { "model": { "linear": { "coeffs": [0.5, 2.0] } } },
+                    "usage": { "vector": [DURATION_COUNTER] },
+                }
+            }
+        }))
+        .unwrap();
+        assert_eq!(nested.coeffs, vec![0.5]);
+        assert_eq!(nested.fixed, 2.0);
+    }
+
+    #[test]
+    fn pricing_with_missing_or_mismatched_coeffs_is_rejected() {
+        assert!(LinearPricing::from_properties(&json!({
+            "golem.com.usage.vector": [DURATION_COUNTER],
+        }))
+        .is_err());
+        assert!(LinearPricing::from_properties(&json!({
+            "golem.com.pricing.model.linear.coeffs": [0.1, 0.2, 1.0],
+            "golem.com.usage.vector": [DURATION_COUNTER],
+        }))
+        .is_err());
+        assert!(LinearPricing::from_properties(&json!({
+            "golem.com.pricing.model.linear.coeffs": "0.1",
+            "golem.com.usage.vector": [DURATION_COUNTER],
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn estimate_uses_usage_delta() {
+        let pricing = pricing();
+        let (duration, cpu, cost) = estimate(
+            Some(&pricing),
+            Some(&[10.0, 5.0][..]),
+            Some(&[40.0, 25.0][..]),
+        );
+        assert_eq!(duration, Some(30.0));
+        assert_eq!(cpu, Some(20.0));
+        assert!((cost.unwrap() - 7.0).abs() < 1e-9);
+
+        assert_eq!(
+            estimate(None, Some(&[0.0][..]), Some(&[1.0][..])),
+            (None, None, None)
+        );
+        assert_eq!(
+            estimate(Some(&pricing), None, Some(&[1.0][..])),
+            (None, None, None)
+        );
+    }
+
+    #[test]
+    fn estimate_treats_missing_counters_before_as_zero() {
+        let (duration, cpu, cost) =
+            estimate(Some(&pricing()), Some(&[10.0][..]), Some(&[40.0, 25.0][..]));
+        assert_eq!(duration, Some(30.0));
+        assert_eq!(cpu, Some(25.0));
+        assert!((cost.unwrap() - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn load_skips_truncated_last_line() {
+        let ledger = ledger("truncated");
+        ledger.record(&job_cost(1, 6, "0xa", Some(0.5))).unwrap();
+        ledger.record(&job_cost(2, 6, "0xa", Some(1.5))).unwrap();
+
+        let path = workdir().join("truncated-costs.jsonl");
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"block_id":3,"job_id":3,"block_si"#)
+            .unwrap();
+
+        let costs = ledger.load().unwrap();
+        assert_eq!(costs.len(), 2);
+        assert_eq!(costs[1].job_id, 2);
+        assert_eq!(ledger.max_cost(), 1.5);
+    }
+
+    #[test]
+    fn missing_ledger_is_empty() {
+        let ledger = ledger("missing");
+        assert!(ledger.load().unwrap().is_empty());
+        assert_eq!(ledger.max_cost(), 0.0);
+    }
+
+    #[test]
+    fn max_cost_follows_recorded_jobs() {
+        let ledger = ledger("running-max");
+        ledger.record(&job_cost(1, 6, "0xa", Some(2.0))).unwrap();
+        assert_eq!(ledger.max_cost(), 2.0);
+
+        ledger.record(&job_cost(2, 6, "0xa", Some(3.0))).unwrap();
+        ledger.record(&job_cost(3, 6, "0xa", Some(1.0))).unwrap();
+        ledger.record(&job_cost(4, 6, "0xa", None)).unwrap();
+        assert_eq!(ledger.max_cost(), 3.0);
+    }
+
+    #[test]
+    fn report_groups_costs() {
+        let mut local = job_cost(3, 30, "local", None);
+        local.duration_sec = None;
+        let costs = vec![
+            job_cost(1, 6, "0xa", Some(1.0)),
+            job_cost(2, 6, "0xb", Some(3.0)),
+            local,
+        ];
+
+        let report = CostReport::new(&costs);
+        let size = &report.by_block_size[&6];
+        assert_eq!(size.jobs, 2);
+        assert_eq!(size.unpriced, 0);
+        assert_eq!(size.total_cost, 4.0);
+        assert_eq!(size.mean_cost, Some(2.0));
+        assert_eq!(size.mean_duration_sec, Some(10.0));
+
+        let unpriced = &report.by_block_size[&30];
+        assert_eq!(unpriced.unpriced, 1);
+        assert_eq!(unpriced.mean_cost, None);
+        assert_eq!(unpriced.mean_duration_sec, None);
+
+        assert_eq!(report.by_provider.len(), 3);
+        assert_eq!(report.by_subnet["-"].jobs, 3);
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{anyhow, bail};
+use serde::Deserialize;
+use structopt::StructOpt;
+use url::Url;
+
+use ya_client::activity::ActivityRequestorApi;
+use ya_client::market::MarketRequestorApi;
+use ya_client::payment::PaymentRequestorApi;
+use ya_client::web::WebClient;
+
+/// Connection to yagna daemon, which can run on other machine.
+#[derive(StructOpt, Debug, Clone)]
+pub struct DaemonArgs {
+    #[structopt(long, env = "YAGNA_APPKEY")]
+    pub appkey: String,
+    /// Base url of yagna REST API. Service urls are derived from it.
+    #[structopt(long, env = "YAGNA_API_URL")]
+    pub yagna_api_url: Option<Url>,
+    #[structopt(long, env = "YAGNA_MARKET_URL")]
+    pub market_url: Option<Url>,
+    /// Activities are created by yarapi, which reads this url only from
+    /// environment, so it must be set as `YAGNA_ACTIVITY_URL`.
+    #[structopt(long, env = "YAGNA_ACTIVITY_URL")]
+    pub activity_url: Option<Url>,
+    #[structopt(long, env = "YAGNA_PAYMENT_URL")]
+    pub payment_url: Option<Url>,
+    /// Service bus of yagna daemon. Used for transferring files to Providers.
+    /// gftp reads it only from environment, so it must be set as `GSB_URL`.
+    #[structopt(long, env = "GSB_URL")]
+    pub gsb_url: Option<Url>,
+    /// Requestor node id. Must be identity of app key. Payments are made from this
+    /// address, so requestors sharing daemon can pay from different accounts.
+    #[structopt(long, env = "YAGNA_ID")]
+    pub identity: Option<String>,
+}
+
+/// Response of yagna `/me` endpoint.
+#[derive(Deserialize)]
+struct Me {
+    identity: String,
+}
+
+impl DaemonArgs {
+    pub fn client(&self) -> anyhow::Result<WebClient> {
+        // yarapi and gftp create their own connections based on environment.
+        // Options given only on command line wouldn't reach them.
+        let env_only = [
+            ("YAGNA_ACTIVITY_URL", &self.activity_url),
+            ("GSB_URL", &self.gsb_url),
+        ];
+        for (name, url) in env_only.iter() {
+            if let Some(url) = url {
+                match std::env::var(name).map(|value| Url::parse(&value)) {
+                    Ok(Ok(value)) if &value == url => (),
+                    _ => bail!("{} must be set in environment, not on command line.", name),
+                }
+            }
+        }
+
+        let mut builder = WebClient::builder().auth_token(&self.appkey);
+        if let Some(url) = &self.yagna_api_url {
+            builder = builder.api_url(url.clone());
+        }
+
+        log::info!(
+            "Using yagna daemon at {} as {}.",
+            self.yagna_api_url
+                .as_ref()
+                .map(|url| url.to_string())
+                .unwrap_or_else(|| "default url".to_string()),
+            self.identity
+                .as_deref()
+                .unwrap_or("app key default identity")
+        );
+        Ok(builder.build())
+    }
+
+    pub fn market_api(&self, client: &WebClient) -> anyhow::Result<MarketRequestorApi> {
+        Ok(client.interface_at(self.market_url.clone())?)
+    }
+
+    pub fn activity_api(&self, client: &WebClient) -> anyhow::Result<ActivityRequestorApi> {
+        Ok(client.interface_at(self.activity_url.clone())?)
+    }
+
+    pub fn payment_api(&self, client: &WebClient) -> anyhow::Result<PaymentRequestorApi> {
+        Ok(client.interface_at(self.payment_url.clone())?)
+    }
+
+    /// App key determines identity, we act as. Configured identity, that
+    /// doesn't match it, would make us pay from the wrong account.
+    pub async fn check_identity(&self, client: &WebClient) -> anyhow::Result<()> {
+        let identity = match &self.identity {
+            Some(identity) => identity,
+            None => return Ok(()),
+        };
+
+        let me: Me = client
+            .get("me")
+            .send()
+            .json()
+            .await
+            .map_err(|e| anyhow!("Can't read identity of app key. Error: {}", e))?;
+        if !me.identity.eq_ignore_ascii_case(identity) {
+            bail!(
+                "Configured identity {} doesn't match app key identity {}.",
+                identity,
+                me.identity
+            );
+        }
+        Ok(())
+    }
+}
@@ -1,33 +1,63 @@
+mod auth;
+mod backend;
+mod bench;
+mod costs;
+mod daemon;
+mod market;
+mod offers;
+mod payments;
+mod pool;
 mod prover_runner;
+mod reputation;
+mod resources;
+mod retry;
+#[cfg(test)]
+mod testing;
 mod zksync_client;
 
 use chrono::{DateTime, Utc};
+use futures::FutureExt;
 use std::ops::Add;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
 use url::Url;
 
 use ya_client::web::WebClient;
 use yarapi::requestor::Image;
-use yarapi::rest::{self, Activity};
+use yarapi::rest;
 use yarapi::ya_agreement_utils::{constraints, ConstraintKey, Constraints};
-use zksync_client::ZksyncClient;
+use zksync_client::{ConnectionArgs, RetryArgs, ZksyncClient};
 
-use crate::prover_runner::prove_block;
+use crate::auth::AuthArgs;
+use crate::backend::local::LocalBackend;
+use crate::backend::yagna::YagnaBackend;
+use crate::backend::{BackendKind, ProvingBackend, ReadyBackend};
+use crate::bench::{run_bench, BenchArgs};
+use crate::costs::{print_costs, CostLedger, CostsArgs, COSTS_FILE};
+use crate::daemon::DaemonArgs;
+use crate::market::{Negotiator, PriceArgs, SubnetDemand};
+use crate::offers::{explore_offers, OffersArgs};
+use crate::payments::{PaymentArgs, Payments};
+use crate::pool::{run_pool, Autoscaler, PrefetchArgs, ScaleArgs, Worker};
+use crate::prover_runner::{check_capabilities, supervise_prover};
+use crate::reputation::{Outcome, ReputationArgs, ReputationStore, REPUTATION_FILE};
+use crate::resources::{ResourceGuard, Termination};
+use prover_common::exit_code::INVALID_PROOF_EXIT_CODE;
 use ya_client_model::market::NewDemand;
 
 const PACKAGE: &str =
     "hash:sha3:b491514aa88dc7f79ed461358cf9ea9c63775da591312f2f1a1dc43d:http://yacn.dev.golem.network:8000/ya-zksync-prover-0.2.3";
 
-pub fn create_demand(deadline: DateTime<Utc>, subnet: &str) -> NewDemand {
+pub fn create_demand(deadline: DateTime<Utc>, subnet: &str, package: &str) -> NewDemand {
     log::info!("Using subnet: {}", subnet);
 
     let ts = deadline.timestamp_millis();
     let properties = serde_json::json!({
         "golem.node.id.name": "zk-sync-node",
         "golem.node.debug.subnet": subnet,
-        "golem.srv.comp.task_package": PACKAGE,
+        "golem.srv.comp.task_package": package,
         "golem.srv.comp.expiration": ts
     });
 
@@ -45,14 +75,103 @@ pub fn create_demand(deadline: DateTime<Utc>, subnet: &str) -> NewDemand {
     }
 }
 
+pub fn create_demands(
+    deadline: DateTime<Utc>,
+    subnets: &[String],
+    package: &str,
+) -> Vec<SubnetDemand> {
+    subnets
+        .iter()
+        .map(|subnet| SubnetDemand {
+            subnet: subnet.clone(),
+            demand: create_demand(deadline, subnet, package),
+        })
+        .collect()
+}
+
 #[derive(StructOpt)]
 struct Args {
-    #[structopt(long, env, default_value = "community.3")]
-    subnet: String,
-    #[structopt(long, env = "YAGNA_APPKEY")]
-    appkey: String,
+    /// Subnets to look for Providers in. Comma separated.
+    #[structopt(
+        long = "subnet",
+        env = "SUBNET",
+        default_value = "community.3",
+        use_delimiter = true
+    )]
+    subnets: Vec<String>,
     #[structopt(long, env)]
     server_api_url: String,
+    /// Name identifying this prover on zksync server.
+    #[structopt(long, env, default_value = "yagna-node-1")]
+    worker_name: String,
+    /// Where to run prover: `yagna`, `local` or `auto` (yagna Provider
+    /// with local prover as fallback).
+    #[structopt(long, env, default_value = "yagna")]
+    backend: BackendKind,
+    /// Prover image for yagna Providers in `hash:sha3:<hash>:<url>` format.
+    /// Image must be built from this repository (see `docker/build-dockers.sh`),
+    /// because Requestor relies on `check-keys` mode and result manifests.
+    #[structopt(long, env)]
+    prover_package: Option<String>,
+    /// Path to yagna-prover binary used by local backend.
+    #[structopt(long, env, default_value = "yagna-prover", parse(from_os_str))]
+    local_prover: PathBuf,
+    /// Let prover communicate with zksync server directly. Requires Providers
+    /// allowing outbound network traffic. Requestor only supervises prover execution.
+    #[structopt(long, env)]
+    direct_network: bool,
+    /// Seconds to negotiate agreement with yagna Provider in `auto` mode before
+    /// falling back to local prover.
+    #[structopt(long, env, default_value = "300")]
+    local_fallback_timeout: u64,
+    /// Seconds after which prover is considered hanging and Provider gets
+    /// timeout in reputation.
+    #[structopt(long, env, default_value = "3600")]
+    prover_timeout: u64,
+    /// Number of prover exits in a row in direct network mode, after which
+    /// Requestor gives up. Run longer than `prover-timeout` resets the counter.
+    #[structopt(long, env, default_value = "5")]
+    max_prover_restarts: u32,
+    /// Send uploads, prover run and downloads of each job to Provider as single ExeScript batch.
+    #[structopt(long, env)]
+    batch_job: bool,
+    #[structopt(flatten)]
+    daemon: DaemonArgs,
+    #[structopt(flatten)]
+    retry: RetryArgs,
+    #[structopt(flatten)]
+    auth: AuthArgs,
+    #[structopt(flatten)]
+    connection: ConnectionArgs,
+    #[structopt(flatten)]
+    payment: PaymentArgs,
+    #[structopt(flatten)]
+    prices: PriceArgs,
+    #[structopt(flatten)]
+    reputation: ReputationArgs,
+    #[structopt(flatten)]
+    scale: ScaleArgs,
+    #[structopt(flatten)]
+    prefetch: PrefetchArgs,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Proves sample blocks bundled with prover image on selected backend
+    /// and prints timings. Doesn't communicate with zksync server.
+    Bench(BenchArgs),
+    /// Summarizes estimated costs of proven blocks per block size and provider.
+    Costs(CostsArgs),
+    /// Collects offers matching our demand without signing agreements.
+    Offers(OffersArgs),
+}
+
+impl Args {
+    fn package(&self) -> &str {
+        self.prover_package.as_deref().unwrap_or(PACKAGE)
+    }
 }
 
 #[actix_rt::main]
@@ -67,72 +186,313 @@ pub async fn main() -> anyhow::Result<()> {
         .filter_module("ya_service_bus::remote_router", log::LevelFilter::Off)
         .init();
 
-    let server_api_url: Url = args.server_api_url.parse()?;
-    let zksync_client = ZksyncClient::new(&server_api_url, "yagna-node-1", Duration::from_secs(69));
+    let ledger = CostLedger::new(Path::new(COSTS_FILE));
+    if let Some(Command::Costs(costs)) = &args.command {
+        return print_costs(&ledger, costs);
+    }
+    let reputation = Arc::new(ReputationStore::load(
+        Path::new(REPUTATION_FILE),
+        args.reputation.clone(),
+    )?);
 
-    let client = WebClient::with_token(&args.appkey);
+    let client = args.daemon.client()?;
+    args.daemon.check_identity(&client).await?;
     let session = rest::Session::with_client(client.clone());
-    let market = session.market()?;
+    let guard = Arc::new(ResourceGuard::new(args.daemon.market_api(&client)?));
 
-    let deadline = Utc::now().add(chrono::Duration::minutes(25));
-    let demand = create_demand(deadline, &args.subnet);
+    if let Some(Command::Offers(offers)) = &args.command {
+        let deadline = Utc::now().add(chrono::Duration::minutes(25));
+        let demands = create_demands(deadline, &args.subnets, args.package());
+        let result = session.with(explore_offers(&guard, &demands, offers)).await;
+        guard.release_all(&termination(&result)).await;
+        return result.unwrap_or_else(|| anyhow::bail!("ctrl-c caught"));
+    }
 
-    let subscription = market.subscribe_demand(demand.clone()).await?;
-    log::info!("Created subscription [{}]", subscription.id().as_ref());
+    // Local prover doesn't need payments.
+    let payments = match args.backend {
+        BackendKind::Local => None,
+        _ => {
+            let payments = Payments::new(&client, &args.daemon, &args.payment).await?;
+            actix_rt::spawn(payments.clone().process_events());
+            Some(payments)
+        }
+    };
 
-    log::info!("Registering prover..");
-    let prover_id = zksync_client.register_prover(0).await?;
-    log::info!("Registered prover under id [{}].", prover_id);
+    let work = async {
+        if let Some(Command::Bench(bench)) = &args.command {
+            let backend = create_backend(
+                &args,
+                &client,
+                &session,
+                payments.as_deref(),
+                &reputation,
+                &guard,
+            )
+            .await?;
+            return run_bench(backend.backend.as_ref(), bench).await;
+        }
 
-    let agreements = subscription
-        .negotiate_agreements(demand, 1, deadline)
-        .await?;
-    let activity = Arc::new(session.create_activity(&agreements[0]).await?);
-
-    session
-        .with(async {
-            log::info!("Deploying image and starting ExeUnit...");
-            if let Err(e) = activity
-                .execute_commands(vec![
-                    rest::ExeScriptCommand::Deploy {},
-                    rest::ExeScriptCommand::Start { args: vec![] },
-                ])
-                .await
-            {
-                log::error!("Failed to initialize yagna task. Error: {}.", e);
-                return Ok(());
-            };
+        let server_api_url: Url = args.server_api_url.parse()?;
+        let zksync_client = ZksyncClient::new(
+            &server_api_url,
+            &args.worker_name,
+            Duration::from_secs(69),
+            args.retry.clone(),
+            args.auth.clone().into(),
+            &args.connection,
+        )?;
 
-            log::info!("Image deployed. ExeUnit started.");
+        // In direct network mode prover registers on server by itself.
+        if !args.direct_network {
+            log::info!("Registering prover..");
+            let prover_id = zksync_client.register_prover(0).await?;
+            log::info!("Registered prover under id [{}].", prover_id);
+            guard.add_prover(zksync_client.clone(), prover_id);
+        }
 
+        let ready = create_backend(
+            &args,
+            &client,
+            &session,
+            payments.as_deref(),
+            &reputation,
+            &guard,
+        )
+        .await?;
+
+        // In direct network mode we don't see, how much work is waiting.
+        if args.direct_network {
+            let backend = ready.backend;
+            let config = zksync_client::network_config(&args.retry, &args.auth, &args.connection)?;
+            let healthy_run = Duration::from_secs(args.prover_timeout);
+            let mut restarts = 0;
             loop {
-                match prove_block(zksync_client.clone(), activity.clone())
+                if let Some(payments) = &payments {
+                    if !payments.can_take_block(ledger.max_cost()) {
+                        log::warn!(
+                            "Budget {} exhausted. Not taking new blocks.",
+                            args.payment.budget
+                        );
+                        return Ok(());
+                    }
+                }
+
+                let started = Instant::now();
+                match supervise_prover(backend.clone(), &server_api_url, &args.worker_name, &config)
                     .await
-                    .map_err(|e| log::warn!("{}", e))
                 {
-                    Err(_) => tokio::time::delay_for(Duration::from_secs(10)).await,
-                    Ok(()) => (),
+                    Ok(Some(0)) => log::info!("Prover on {} exited. Restarting..", backend.name()),
+                    Ok(Some(INVALID_PROOF_EXIT_CODE)) => {
+                        log::warn!(
+                            "Proof computed on {} didn't pass verification. Restarting..",
+                            backend.name()
+                        );
+                        reputation.record(&backend.provider(), Outcome::InvalidProof);
+                    }
+                    Ok(return_code) => {
+                        log::warn!(
+                            "Prover on {} exited with code {:?}. Restarting..",
+                            backend.name(),
+                            return_code
+                        );
+                        reputation.record(&backend.provider(), Outcome::Failed);
+                    }
+                    Err(e) => {
+                        log::warn!("{}", e);
+                        reputation.record(&backend.provider(), Outcome::Failed);
+                    }
+                }
+                if let Some(payments) = &payments {
+                    payments.track_usage(backend.as_ref()).await;
+                }
+
+                restarts = match started.elapsed() > healthy_run {
+                    true => 1,
+                    false => restarts + 1,
+                };
+                if restarts > args.max_prover_restarts {
+                    anyhow::bail!(
+                        "Prover on {} exited {} times in a row. Giving up.",
+                        backend.name(),
+                        restarts
+                    );
                 }
+                tokio::time::delay_for(Duration::from_secs(10)).await;
             }
+        }
+
+        let autoscaler = Autoscaler::new(args.scale.clone());
+        let worker = Worker {
+            zksync_client: zksync_client.clone(),
+            ledger: &ledger,
+            reputation: &reputation,
+            payments: payments.as_deref(),
+            autoscaler: &autoscaler,
+            guard: &guard,
+            prefetch: args.prefetch.clone(),
+            prover_timeout: Duration::from_secs(args.prover_timeout),
+            batch_job: args.batch_job,
+        };
+        run_pool(ready, &worker, || {
+            create_backend(
+                &args,
+                &client,
+                &session,
+                payments.as_deref(),
+                &reputation,
+                &guard,
+            )
+            .boxed_local()
         })
-        .await
-        .unwrap_or_else(|| anyhow::bail!("ctrl-c caught"))
-        .map_err(|e| log::info!("{}", e))
-        .ok();
-
-    log::info!("Destroying activity..");
-    activity
-        .destroy()
-        .await
-        .map_err(|e| log::error!("Can't destroy activity. Error: {}", e))
-        .ok();
-
-    log::info!("Stopping prover on zksync server..");
-    zksync_client
-        .prover_stopped(prover_id)
-        .await
-        .map_err(|e| log::error!("Failed to unregister prover on server. Error: {}", e))
-        .ok();
-
-    Ok(())
+        .await;
+        Ok(())
+    };
+
+    let result = session.with(work).await;
+    guard.release_all(&termination(&result)).await;
+
+    if let Some(payments) = &payments {
+        payments
+            .finish(Duration::from_secs(args.payment.invoice_timeout))
+            .await;
+    }
+
+    result.unwrap_or_else(|| {
+        log::info!("ctrl-c caught");
+        Ok(())
+    })
+}
+
+/// Reason of terminating agreements, that are left after requestor finished.
+fn termination(result: &Option<anyhow::Result<()>>) -> Termination {
+    match result {
+        Some(Ok(())) => Termination::Finished,
+        Some(Err(e)) => Termination::Failed(e.to_string()),
+        None => Termination::Interrupted,
+    }
+}
+
+async fn create_backend(
+    args: &Args,
+    client: &WebClient,
+    session: &rest::Session,
+    payments: Option<&Payments>,
+    reputation: &Arc<ReputationStore>,
+    guard: &Arc<ResourceGuard>,
+) -> anyhow::Result<ReadyBackend> {
+    match args.backend {
+        BackendKind::Yagna => {
+            create_yagna_backend(args, client, session, payments, reputation, guard, None).await
+        }
+        BackendKind::Local => create_local_backend(&args.local_prover, guard).await,
+        BackendKind::Auto => {
+            // Timeout is applied inside negotiation, so subscriptions are
+            // removed, before we fall back.
+            let fallback_timeout = Duration::from_secs(args.local_fallback_timeout);
+            match create_yagna_backend(
+                args,
+                client,
+                session,
+                payments,
+                reputation,
+                guard,
+                Some(fallback_timeout),
+            )
+            .await
+            {
+                Ok(backend) => Ok(backend),
+                Err(e) => {
+                    log::warn!(
+                        "Can't use yagna Provider: {}. Falling back to local prover.",
+                        e
+                    );
+                    create_local_backend(&args.local_prover, guard).await
+                }
+            }
+        }
+    }
+}
+
+async fn create_yagna_backend(
+    args: &Args,
+    client: &WebClient,
+    session: &rest::Session,
+    payments: Option<&Payments>,
+    reputation: &Arc<ReputationStore>,
+    guard: &Arc<ResourceGuard>,
+    negotiation_timeout: Option<Duration>,
+) -> anyhow::Result<ReadyBackend> {
+    let deadline = Utc::now().add(chrono::Duration::minutes(25));
+    let demands = create_demands(deadline, &args.subnets, args.package());
+
+    let negotiator = Negotiator::new(guard.clone(), args.prices.clone(), reputation.clone());
+    let agreements = negotiator
+        .negotiate(&demands, 1, deadline, negotiation_timeout)
+        .await?;
+    let agreement = &agreements[0];
+    let activity = match session.create_activity(&agreement.agreement_id).await {
+        Ok(activity) => Arc::new(activity),
+        Err(e) => {
+            let reason = format!("Can't create activity. Error: {}", e);
+            guard
+                .terminate_agreement(
+                    &agreement.agreement_id,
+                    &Termination::Failed(reason.clone()),
+                )
+                .await;
+            anyhow::bail!(reason);
+        }
+    };
+
+    let yagna = Arc::new(YagnaBackend::new(
+        activity,
+        args.daemon.activity_api(client)?,
+        agreement,
+    ));
+    let backend: Arc<dyn ProvingBackend> = yagna.clone();
+    guard.add_backend(backend.clone());
+    if let Some(payments) = payments {
+        payments.register_agreement(&agreement.agreement_id, yagna.pricing().cloned());
+    }
+    if let Err(e) = yagna.deploy().await {
+        reputation.record(&agreement.offer.provider_id, Outcome::Failed);
+        let reason = format!("Failed to initialize yagna task. Error: {}", e);
+        guard
+            .release_backend(&backend, &Termination::Failed(reason.clone()))
+            .await;
+        anyhow::bail!(reason);
+    }
+    check_backend(backend, guard).await
+}
+
+async fn create_local_backend(
+    binary: &Path,
+    guard: &ResourceGuard,
+) -> anyhow::Result<ReadyBackend> {
+    let backend: Arc<dyn ProvingBackend> = Arc::new(LocalBackend::new(binary)?);
+    guard.add_backend(backend.clone());
+    check_backend(backend, guard).await
+}
+
+/// Backend without keys for any block size can't prove anything.
+async fn check_backend(
+    backend: Arc<dyn ProvingBackend>,
+    guard: &ResourceGuard,
+) -> anyhow::Result<ReadyBackend> {
+    let reason = match check_capabilities(backend.clone()).await {
+        Ok(report) if !report.ready_sizes.is_empty() => {
+            return Ok(ReadyBackend {
+                backend,
+                block_sizes: report.ready_sizes,
+            })
+        }
+        Ok(_) => "no keys for any block size".to_string(),
+        Err(e) => e.to_string(),
+    };
+
+    let reason = format!("{} can't prove blocks: {}.", backend.name(), reason);
+    guard
+        .release_backend(&backend, &Termination::Failed(reason.clone()))
+        .await;
+    anyhow::bail!(reason)
 }
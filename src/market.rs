@@ -0,0 +1,552 @@
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use structopt::StructOpt;
+
+use ya_client::market::MarketRequestorApi;
+use ya_client_model::market::{
+    proposal::State, AgreementProposal, NewDemand, NewProposal, Proposal, RequestorEvent,
+};
+
+use crate::costs::{property, LinearPricing, CPU_COUNTER, DURATION_COUNTER};
+use crate::reputation::ReputationStore;
+use crate::resources::ResourceGuard;
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct PriceArgs {
+    /// Offers charging more for hour of CPU time are rejected.
+    #[structopt(long, env)]
+    pub max_price_per_cpu_hour: Option<f64>,
+    /// Offers charging more for hour of activity duration are rejected.
+    #[structopt(long, env)]
+    pub max_price_per_hour: Option<f64>,
+    /// Expected time of computing single proof in seconds. Offers are
+    /// compared by price of this computation.
+    #[structopt(long, env, default_value = "600")]
+    pub expected_proof_time: u64,
+    /// Seconds to collect offers before choosing the cheapest ones.
+    #[structopt(long, env, default_value = "20")]
+    pub offer_collect_time: u64,
+}
+
+/// Provider offer with information needed to choose between offers.
+#[derive(Clone, Debug, Serialize)]
+pub struct OfferInfo {
+    pub proposal_id: String,
+    pub provider_id: String,
+    pub name: Option<String>,
+    pub threads: Option<u32>,
+    pub memory_gib: Option<f64>,
+    pub storage_gib: Option<f64>,
+    pub runtime_version: Option<String>,
+    pub subnet: Option<String>,
+    pub pricing: Option<LinearPricing>,
+}
+
+impl OfferInfo {
+    pub fn from_proposal(proposal: &Proposal) -> OfferInfo {
+        let properties = &proposal.properties;
+        OfferInfo {
+            proposal_id: proposal.proposal_id.clone(),
+            provider_id: proposal.issuer_id.to_string(),
+            name: property(properties, "golem.node.id.name").ok(),
+            threads: property(properties, "golem.inf.cpu.threads").ok(),
+            memory_gib: property(properties, "golem.inf.mem.gib").ok(),
+            storage_gib: property(properties, "golem.inf.storage.gib").ok(),
+            runtime_version: property(properties, "golem.runtime.version").ok(),
+            subnet: property(properties, "golem.node.debug.subnet").ok(),
+            pricing: LinearPricing::from_properties(properties).ok(),
+        }
+    }
+
+    pub fn price_per_cpu_hour(&self) -> Option<f64> {
+        self.pricing
+            .as_ref()
+            .and_then(|pricing| pricing.coeff(CPU_COUNTER))
+            .map(|coeff| coeff * 3600.0)
+    }
+
+    pub fn price_per_hour(&self) -> Option<f64> {
+        self.pricing
+            .as_ref()
+            .and_then(|pricing| pricing.coeff(DURATION_COUNTER))
+            .map(|coeff| coeff * 3600.0)
+    }
+
+    /// Price of computing for `secs` seconds. Prover uses all available threads,
+    /// so we expect CPU time to be duration multiplied by threads.
+    pub fn expected_cost(&self, secs: f64) -> Option<f64> {
+        let pricing = self.pricing.as_ref()?;
+        let cpu_secs = secs * self.threads.unwrap_or(1) as f64;
+        let usage = pricing
+            .usage_vector
+            .iter()
+            .map(|counter| match counter.as_str() {
+                DURATION_COUNTER => secs,
+                CPU_COUNTER => cpu_secs,
+                _ => 0.0,
+            })
+            .collect::<Vec<_>>();
+        Some(pricing.fixed + pricing.cost(&usage))
+    }
+
+    /// Returns reason, why offer isn't acceptable.
+    pub fn check_price(&self, limits: &PriceArgs) -> Result<(), String> {
+        if self.pricing.is_none() {
+            return Err("offer has no linear pricing".to_string());
+        }
+        if let (Some(limit), Some(price)) =
+            (limits.max_price_per_cpu_hour, self.price_per_cpu_hour())
+        {
+            if price > limit {
+                return Err(format!("{} per CPU hour exceeds {}", price, limit));
+            }
+        }
+        if let (Some(limit), Some(price)) = (limits.max_price_per_hour, self.price_per_hour()) {
+            if price > limit {
+                return Err(format!("{} per hour exceeds {}", price, limit));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Agreement approved by Provider.
+#[derive(Clone, Debug)]
+pub struct NegotiatedAgreement {
+    pub agreement_id: String,
+    /// Subnet of subscription, the offer came from.
+    pub subnet: String,
+    pub offer: OfferInfo,
+}
+
+/// Providers accept only demands from their own subnet, so we need separate
+/// demand for each subnet.
+#[derive(Clone, Debug)]
+pub struct SubnetDemand {
+    pub subnet: String,
+    pub demand: NewDemand,
+}
+
+pub struct Subscription {
+    pub id: String,
+    pub subnet: String,
+    counter: NewProposal,
+}
+
+/// Lowest score used in ranking, so Providers, that failed every job, are
+/// ranked last instead of getting infinite or NaN cost.
+const MIN_SCORE: f64 = 0.01;
+
+/// Cost divided by success rate is cost of single successful proof.
+fn penalized_cost(cost: f64, score: f64) -> f64 {
+    cost / score.max(MIN_SCORE)
+}
+
+/// Expected cost of successful proof. Providers, that proved blocks before,
+/// are judged by their real proving time and success rate.
+fn rank(offer: &OfferInfo, reputation: &ReputationStore, expected_time: f64) -> f64 {
+    let time = reputation
+        .stats(&offer.provider_id)
+        .and_then(|stats| stats.mean_duration_sec())
+        .unwrap_or(expected_time);
+    let cost = offer.expected_cost(time).unwrap_or(f64::INFINITY);
+    penalized_cost(cost, reputation.score(&offer.provider_id))
+}
+
+/// Negotiates agreements with the cheapest Providers, that fit in price limits
+/// and have good reputation.
+pub struct Negotiator {
+    guard: Arc<ResourceGuard>,
+    prices: PriceArgs,
+    reputation: Arc<ReputationStore>,
+}
+
+impl Negotiator {
+    pub fn new(
+        guard: Arc<ResourceGuard>,
+        prices: PriceArgs,
+        reputation: Arc<ReputationStore>,
+    ) -> Negotiator {
+        Negotiator {
+            guard,
+            prices,
+            reputation,
+        }
+    }
+
+    /// Negotiation interrupted by `timeout` still removes its subscriptions.
+    /// Agreements waiting for approval are left to `ResourceGuard`.
+    pub async fn negotiate(
+        &self,
+        demands: &[SubnetDemand],
+        count: usize,
+        deadline: DateTime<Utc>,
+        timeout: Option<Duration>,
+    ) -> anyhow::Result<Vec<NegotiatedAgreement>> {
+        let subscriptions = subscribe(&self.guard, demands).await?;
+        let negotiation = self.negotiate_on(&subscriptions, count, deadline);
+        let result = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, negotiation)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(anyhow!(
+                        "No agreement negotiated in {}s.",
+                        timeout.as_secs()
+                    ))
+                }),
+            None => negotiation.await,
+        };
+        unsubscribe(&self.guard, &subscriptions).await;
+        result
+    }
+
+    async fn negotiate_on(
+        &self,
+        subscriptions: &[Subscription],
+        count: usize,
+        deadline: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<NegotiatedAgreement>> {
+        let collect_time = Duration::from_secs(self.prices.offer_collect_time);
+        let expected_time = self.prices.expected_proof_time as f64;
+
+        let mut agreements = vec![];
+        while agreements.len() < count {
+            if Utc::now() > deadline {
+                bail!("No acceptable offers found before deadline.");
+            }
+
+            // Offers countered by Provider are ready for agreement.
+            let mut drafts = HashMap::new();
+            for (subscription, proposal) in
+                collect_all(self.guard.market(), subscriptions, collect_time).await
+            {
+                let offer = OfferInfo::from_proposal(&proposal);
+                if let Err(reason) = offer
+                    .check_price(&self.prices)
+                    .and_then(|_| self.reputation.check_provider(&offer.provider_id))
+                {
+                    log::debug!("Skipping offer from [{}]: {}", offer.provider_id, reason);
+                    continue;
+                }
+
+                match proposal.state {
+                    State::Initial => {
+                        self.guard
+                            .market()
+                            .counter_proposal(
+                                &subscription.counter,
+                                &subscription.id,
+                                &proposal.proposal_id,
+                            )
+                            .await
+                            .map_err(|e| log::debug!("Can't counter proposal. Error: {}", e))
+                            .ok();
+                    }
+                    State::Draft => {
+                        drafts.insert(offer.provider_id.clone(), (offer, subscription));
+                    }
+                    _ => (),
+                }
+            }
+
+            let mut drafts = drafts
+                .into_iter()
+                .map(|(_, draft)| draft)
+                .collect::<Vec<_>>();
+            drafts.sort_by(|(a, _), (b, _)| {
+                rank(a, &self.reputation, expected_time)
+                    .partial_cmp(&rank(b, &self.reputation, expected_time))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+
+            for (offer, subscription) in drafts {
+                if agreements.len() >= count {
+                    break;
+                }
+                match self.create_agreement(&offer, deadline).await {
+                    Ok(agreement_id) => {
+                        log::info!(
+                            "Agreement [{}] with [{}] in subnet {} approved. Expected proof cost: {:?}.",
+                            agreement_id,
+                            offer.provider_id,
+                            subscription.subnet,
+                            offer.expected_cost(expected_time)
+                        );
+                        agreements.push(NegotiatedAgreement {
+                            agreement_id,
+                            subnet: subscription.subnet.clone(),
+                            offer,
+                        });
+                    }
+                    Err(e) => {
+                        log::info!("Agreement with [{}] not approved. {}", offer.provider_id, e)
+                    }
+                }
+            }
+        }
+        Ok(agreements)
+    }
+
+    async fn create_agreement(
+        &self,
+        offer: &OfferInfo,
+        deadline: DateTime<Utc>,
+    ) -> anyhow::Result<String> {
+        let api = self.guard.market();
+        let agreement_id = api
+            .create_agreement(&AgreementProposal::new(offer.proposal_id.clone(), deadline))
+            .await?;
+        // Registered before approval, so it is cancelled, even if negotiation
+        // is interrupted in the middle.
+        self.guard.add_pending_agreement(&agreement_id);
+
+        let approval = async {
+            api.confirm_agreement(&agreement_id, None).await?;
+            api.wait_for_approval(&agreement_id, Some(15.0)).await?;
+            Ok::<_, anyhow::Error>(())
+        };
+        if let Err(e) = approval.await {
+            self.guard.cancel_agreement(&agreement_id).await;
+            bail!("Approval of [{}] failed. {}", agreement_id, e);
+        }
+
+        self.guard.add_agreement(&agreement_id);
+        Ok(agreement_id)
+    }
+}
+
+/// Subscribes all demands. Fails only if no demand could be subscribed.
+pub async fn subscribe(
+    guard: &ResourceGuard,
+    demands: &[SubnetDemand],
+) -> anyhow::Result<Vec<Subscription>> {
+    let mut subscriptions = vec![];
+    for SubnetDemand { subnet, demand } in demands {
+        match guard.subscribe(demand).await {
+            Ok(id) => {
+                log::info!("Created subscription [{}] in subnet {}.", id, subnet);
+                subscriptions.push(Subscription {
+                    id,
+                    subnet: subnet.clone(),
+                    counter: NewProposal {
+                        properties: demand.properties.clone(),
+                        constraints: demand.constraints.clone(),
+                    },
+                });
+            }
+            Err(e) => log::warn!("Can't subscribe demand in subnet {}. Error: {}", subnet, e),
+        }
+    }
+
+    if subscriptions.is_empty() {
+        bail!("Failed to subscribe demand in any subnet.");
+    }
+    Ok(subscriptions)
+}
+
+pub async fn unsubscribe(guard: &ResourceGuard, subscriptions: &[Subscription]) {
+    for subscription in subscriptions {
+        guard.unsubscribe(&subscription.id).await;
+    }
+}
+
+/// Collects proposals from all subscriptions at the same time.
+pub async fn collect_all<'a>(
+    api: &MarketRequestorApi,
+    subscriptions: &'a [Subscription],
+    time: Duration,
+) -> Vec<(&'a Subscription, Proposal)> {
+    let results = join_all(
+        subscriptions
+            .iter()
+            .map(|subscription| collect(api, &subscription.id, time)),
+    )
+    .await;
+
+    let mut proposals = vec![];
+    for (subscription, result) in subscriptions.iter().zip(results) {
+        match result {
+            Ok(received) => proposals.extend(
+                received
+                    .into_iter()
+                    .map(|proposal| (subscription, proposal)),
+            ),
+            Err(e) => log::warn!(
+                "Can't collect offers in subnet {}. Error: {}",
+                subscription.subnet,
+                e
+            ),
+        }
+    }
+    proposals
+}
+
+/// Collects proposals coming in given time.
+async fn collect(
+    api: &MarketRequestorApi,
+    subscription_id: &str,
+    time: Duration,
+) -> anyhow::Result<Vec<Proposal>> {
+    let until = std::time::Instant::now() + time;
+    let mut proposals = vec![];
+
+    while std::time::Instant::now() < until {
+        let timeout = until.saturating_duration_since(std::time::Instant::now());
+        let events = api
+            .collect(subscription_id, Some(timeout.as_secs_f32()), Some(20))
+            .await?;
+
+        for event in events {
+            if let RequestorEvent::ProposalEvent { proposal, .. } = event {
+                proposals.push(proposal);
+            }
+        }
+    }
+    Ok(proposals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reputation::Outcome;
+    use crate::testing::reputation;
+    use serde_json::json;
+
+    fn offer(provider_id: &str, properties: serde_json::Value) -> OfferInfo {
+        OfferInfo {
+            proposal_id: format!("proposal-{}", provider_id),
+            provider_id: provider_id.to_string(),
+            name: None,
+            threads: Some(4),
+            memory_gib: None,
+            storage_gib: None,
+            runtime_version: None,
+            subnet: None,
+            pricing: LinearPricing::from_properties(&properties).ok(),
+        }
+    }
+
+    /// 0.36 per hour of duration and 0.72 per hour of CPU time, 1.0 for start.
+    fn priced(provider_id: &str) -> OfferInfo {
+        offer(
+            provider_id,
+            json!({
+                "golem.com.pricing.model.linear.coeffs": [0.0001, 0.0002, 1.0],
+                "golem.com.usage.vector": [DURATION_COUNTER, CPU_COUNTER],
+            }),
+        )
+    }
+
+    fn limits(per_cpu_hour: Option<f64>, per_hour: Option<f64>) -> PriceArgs {
+        PriceArgs {
+            max_price_per_cpu_hour: per_cpu_hour,
+            max_price_per_hour: per_hour,
+            expected_proof_time: 600,
+            offer_collect_time: 20,
+        }
+    }
+
+    fn assert_close(value: Option<f64>, expected: f64) {
+        assert!((value.unwrap() - expected).abs() < 1e-9, "{:?}", value);
+    }
+
+    #[test]
+    fn hourly_prices_from_coeffs() {
+        let offer = priced("0xa");
+        assert_close(offer.price_per_hour(), 0.36);
+        assert_close(offer.price_per_cpu_hour(), 0.72);
+    }
+
+    #[test]
+    fn missing_counter_or_malformed_pricing_has_no_price() {
+        let duration_only = offer(
+            "0xa",
+            json!({
+                "golem.com.pricing.model.linear.coeffs": [0.0001, 1.0],
+                "golem.com.usage.vector": [DURATION_COUNTER],
+            }),
+        );
+        assert_eq!(duration_only.price_per_cpu_hour(), None);
+        assert_close(duration_only.price_per_hour(), 0.36);
+
+        let malformed = offer(
+            "0xb",
+            json!({
+                "golem.com.pricing.model.linear.coeffs": [0.0001],
+                "golem.com.usage.vector": [DURATION_COUNTER, CPU_COUNTER],
+            }),
+        );
+        assert!(malformed.pricing.is_none());
+        assert_eq!(malformed.price_per_cpu_hour(), None);
+        assert_eq!(malformed.expected_cost(600.0), None);
+    }
+
+    #[test]
+    fn expected_cost_counts_cpu_time_of_all_threads() {
+        let mut offer = priced("0xa");
+        // 1.0 + 0.0001 * 100 + 0.0002 * 100 * 4
+        assert_close(offer.expected_cost(100.0), 1.09);
+
+        offer.threads = None;
+        assert_close(offer.expected_cost(100.0), 1.03);
+    }
+
+    #[test]
+    fn check_price_against_limits() {
+        let offer = priced("0xa");
+        assert!(offer.check_price(&limits(None, None)).is_ok());
+        assert!(offer.check_price(&limits(Some(1.0), Some(1.0))).is_ok());
+        assert!(offer.check_price(&limits(Some(0.5), None)).is_err());
+        assert!(offer.check_price(&limits(None, Some(0.3))).is_err());
+    }
+
+    #[test]
+    fn check_price_without_pricing_or_counter() {
+        let malformed = offer("0xb", json!({}));
+        assert!(malformed.check_price(&limits(None, None)).is_err());
+
+        // Limit can't be checked without CPU counter, so it doesn't reject offer.
+        let duration_only = offer(
+            "0xa",
+            json!({
+                "golem.com.pricing.model.linear.coeffs": [0.0001, 1.0],
+                "golem.com.usage.vector": [DURATION_COUNTER],
+            }),
+        );
+        assert!(duration_only.check_price(&limits(Some(0.01), None)).is_ok());
+    }
+
+    #[test]
+    fn failing_provider_is_ranked_after_unknown_one() {
+        let reputation = reputation("market-rank");
+        for _ in 0..20 {
+            reputation.record("0xfailing", Outcome::Failed);
+        }
+
+        let failing = rank(&priced("0xfailing"), &reputation, 600.0);
+        let unknown = rank(&priced("0xunknown"), &reputation, 600.0);
+        assert!(failing.is_finite());
+        assert!(failing > unknown);
+
+        let unpriced = rank(&offer("0xunpriced", json!({})), &reputation, 600.0);
+        assert!(unpriced > failing);
+    }
+
+    #[test]
+    fn zero_score_is_ranked_last() {
+        let failing = penalized_cost(1.0, 0.0);
+        assert!(failing.is_finite());
+        assert!(failing > penalized_cost(10.0, 0.5));
+        assert_eq!(penalized_cost(0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn better_score_lowers_cost() {
+        assert!(penalized_cost(1.0, 0.9) < penalized_cost(1.0, 0.5));
+    }
+}
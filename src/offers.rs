@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use structopt::StructOpt;
+
+use crate::costs::format_value;
+use crate::market::{collect_all, subscribe, unsubscribe, OfferInfo, SubnetDemand};
+use crate::resources::ResourceGuard;
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct OffersArgs {
+    /// Seconds to collect offers.
+    #[structopt(long, default_value = "60")]
+    pub collect_time: u64,
+    /// Print offers as json instead of table.
+    #[structopt(long)]
+    pub json: bool,
+}
+
+/// Collects offers matching our demand without negotiating agreements.
+pub async fn explore_offers(
+    guard: &ResourceGuard,
+    demands: &[SubnetDemand],
+    args: &OffersArgs,
+) -> anyhow::Result<()> {
+    let subscriptions = subscribe(guard, demands).await?;
+    log::info!("Collecting offers for {}s.", args.collect_time);
+
+    let proposals = collect_all(
+        guard.market(),
+        &subscriptions,
+        Duration::from_secs(args.collect_time),
+    )
+    .await;
+    unsubscribe(guard, &subscriptions).await;
+
+    // Provider can send the same offer many times.
+    let offers = proposals
+        .iter()
+        .map(|(_, proposal)| OfferInfo::from_proposal(proposal))
+        .map(|offer| (offer.provider_id.clone(), offer))
+        .collect::<BTreeMap<_, _>>();
+
+    if args.json {
+        let offers = offers.values().collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&offers)?);
+    } else {
+        print_offers(offers.values());
+    }
+    Ok(())
+}
+
+fn print_offers<'a>(offers: impl Iterator<Item = &'a OfferInfo>) {
+    println!(
+        "{:<20} {:<42} {:>9} {:>10} {:>7} {:>8} {:<14} {:>10} {:>10} {:>10}",
+        "name",
+        "provider",
+        "mem [GiB]",
+        "disk [GiB]",
+        "threads",
+        "runtime",
+        "subnet",
+        "per hour",
+        "per cpu h",
+        "start"
+    );
+    for offer in offers {
+        println!(
+            "{:<20} {:<42} {:>9} {:>10} {:>7} {:>8} {:<14} {:>10} {:>10} {:>10}",
+            offer.name.as_deref().unwrap_or("-"),
+            offer.provider_id,
+            format_value(offer.memory_gib, 1),
+            format_value(offer.storage_gib, 1),
+            offer
+                .threads
+                .map(|threads| threads.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            offer.runtime_version.as_deref().unwrap_or("-"),
+            offer.subnet.as_deref().unwrap_or("-"),
+            format_value(offer.price_per_hour(), 4),
+            format_value(offer.price_per_cpu_hour(), 4),
+            format_value(offer.pricing.as_ref().map(|pricing| pricing.fixed), 4)
+        );
+    }
+}
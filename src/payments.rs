@@ -0,0 +1,445 @@
+use anyhow::anyhow;
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive, Zero};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+
+use ya_client::activity::ActivityRequestorApi;
+use ya_client::payment::PaymentRequestorApi;
+use ya_client::web::WebClient;
+use ya_client_model::payment::{
+    Acceptance, Allocation, DebitNote, EventType, Invoice, NewAllocation, Rejection,
+    RejectionReason,
+};
+
+use crate::backend::ProvingBackend;
+use crate::costs::LinearPricing;
+use crate::daemon::DaemonArgs;
+
+/// Failed accept or reject of the same debit note or invoice is retried
+/// this many times, before the event is skipped.
+const MAX_EVENT_ATTEMPTS: u32 = 5;
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct PaymentArgs {
+    /// Total amount, we are ready to spend on all Providers.
+    #[structopt(long, env, default_value = "10.0")]
+    pub budget: BigDecimal,
+    #[structopt(long, env, default_value = "zksync-rinkeby-tglm")]
+    pub payment_platform: String,
+    /// Fraction, by which debit notes and invoices can exceed price computed
+    /// from agreement and reported usage.
+    #[structopt(long, env, default_value = "0.05")]
+    pub payment_tolerance: f64,
+    /// Seconds to wait for invoices from Providers on exit.
+    #[structopt(long, env, default_value = "60")]
+    pub invoice_timeout: u64,
+}
+
+/// Terms of agreement, that debit notes and invoices are checked against.
+struct AgreementTerms {
+    pricing: Option<LinearPricing>,
+    /// Highest amount accepted for this agreement so far.
+    accepted: BigDecimal,
+    /// Price of the latest usage, we read from activity ourselves.
+    expected: f64,
+    invoiced: bool,
+}
+
+#[derive(Default)]
+struct PaymentState {
+    agreements: HashMap<String, AgreementTerms>,
+    last_debit_note_event: Option<DateTime<Utc>>,
+    last_invoice_event: Option<DateTime<Utc>>,
+    /// Failed attempts of handling debit notes and invoices by their ids.
+    failed_attempts: HashMap<String, u32>,
+}
+
+/// Accepts debit notes and invoices, that match our agreements, and keeps
+/// spending within budget.
+pub struct Payments {
+    api: PaymentRequestorApi,
+    activity_api: ActivityRequestorApi,
+    allocation: Allocation,
+    budget: BigDecimal,
+    tolerance: f64,
+    state: Mutex<PaymentState>,
+}
+
+impl Payments {
+    /// Allocation is created for configured identity or default identity of app key.
+    pub async fn new(
+        client: &WebClient,
+        daemon: &DaemonArgs,
+        args: &PaymentArgs,
+    ) -> anyhow::Result<Arc<Payments>> {
+        let api = daemon.payment_api(client)?;
+        let allocation = api
+            .create_allocation(&NewAllocation {
+                address: daemon.identity.clone(),
+                payment_platform: Some(args.payment_platform.clone()),
+                total_amount: args.budget.clone(),
+                timeout: None,
+                make_deposit: false,
+            })
+            .await
+            .map_err(|e| anyhow!("Can't create allocation. Error: {}", e))?;
+
+        log::info!(
+            "Created allocation [{}] for {} on {} from {}.",
+            allocation.allocation_id,
+            args.budget,
+            args.payment_platform,
+            allocation.address
+        );
+        Ok(Arc::new(Payments {
+            api,
+            activity_api: daemon.activity_api(client)?,
+            allocation,
+            budget: args.budget.clone(),
+            tolerance: args.payment_tolerance,
+            state: Mutex::new(PaymentState {
+                last_debit_note_event: Some(Utc::now()),
+                last_invoice_event: Some(Utc::now()),
+                ..Default::default()
+            }),
+        }))
+    }
+
+    /// Only debit notes and invoices for registered agreements are accepted.
+    pub fn register_agreement(&self, agreement_id: &str, pricing: Option<LinearPricing>) {
+        self.state.lock().unwrap().agreements.insert(
+            agreement_id.to_string(),
+            AgreementTerms {
+                expected: pricing.as_ref().map(|pricing| pricing.fixed).unwrap_or(0.0),
+                pricing,
+                accepted: BigDecimal::zero(),
+                invoiced: false,
+            },
+        );
+    }
+
+    /// Reads usage counters of backend activity. Invoice sent after activity
+    /// is destroyed includes usage, that no debit note reported.
+    pub async fn track_usage(&self, backend: &dyn ProvingBackend) {
+        let agreement_id = match backend.agreement_id() {
+            Some(agreement_id) => agreement_id,
+            None => return,
+        };
+        match backend.usage().await {
+            Ok(Some(usage)) => self.record_usage(&agreement_id, &usage),
+            Ok(None) => (),
+            Err(e) => log::debug!("Can't read usage of {}. {}", backend.name(), e),
+        }
+    }
+
+    fn record_usage(&self, agreement_id: &str, usage: &[f64]) {
+        if let Some(terms) = self.state.lock().unwrap().agreements.get_mut(agreement_id) {
+            if let Some(pricing) = &terms.pricing {
+                terms.expected = terms.expected.max(pricing.fixed + pricing.cost(usage));
+            }
+        }
+    }
+
+    /// Amount accepted for all agreements.
+    pub fn spent(&self) -> BigDecimal {
+        let state = self.state.lock().unwrap();
+        state
+            .agreements
+            .values()
+            .fold(BigDecimal::zero(), |sum, terms| sum + &terms.accepted)
+    }
+
+    /// Checks if we can afford proving block, that will cost `expected_cost`.
+    pub fn can_take_block(&self, expected_cost: f64) -> bool {
+        let expected = BigDecimal::from_f64(expected_cost).unwrap_or_else(BigDecimal::zero);
+        self.spent() + expected <= self.budget
+    }
+
+    /// Processes payment events until process exits.
+    pub async fn process_events(self: Arc<Self>) {
+        loop {
+            if let Err(e) = self.process_debit_notes().await {
+                log::warn!("Failed to process debit notes. {}", e);
+                tokio::time::delay_for(Duration::from_secs(5)).await;
+            }
+            if let Err(e) = self.process_invoices().await {
+                log::warn!("Failed to process invoices. {}", e);
+                tokio::time::delay_for(Duration::from_secs(5)).await;
+            }
+        }
+    }
+
+    async fn process_debit_notes(&self) -> anyhow::Result<()> {
+        let after = self.state.lock().unwrap().last_debit_note_event;
+        let events = self
+            .api
+            .get_debit_note_events(after.as_ref(), Some(Duration::from_secs(5)))
+            .await?;
+
+        // Event is marked as processed only after it was handled, so failed
+        // accept or reject is retried with next events query.
+        for event in events {
+            if event.event_type == EventType::Received {
+                let handled = self.handle_debit_note(&event.debit_note_id).await;
+                self.check_attempt(&event.debit_note_id, "debit note", handled)?;
+            }
+            self.state.lock().unwrap().last_debit_note_event = Some(event.timestamp);
+        }
+        Ok(())
+    }
+
+    /// Passes error of handling document further, so its event is retried,
+    /// until it fails `MAX_EVENT_ATTEMPTS` times. Then the event is skipped,
+    /// so it doesn't block events after it.
+    fn check_attempt(
+        &self,
+        document_id: &str,
+        document: &str,
+        handled: anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let error = match handled {
+            Ok(()) => {
+                state.failed_attempts.remove(document_id);
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+
+        let attempts = state
+            .failed_attempts
+            .entry(document_id.to_string())
+            .or_insert(0);
+        *attempts += 1;
+        if *attempts < MAX_EVENT_ATTEMPTS {
+            return Err(error);
+        }
+
+        state.failed_attempts.remove(document_id);
+        log::error!(
+            "Skipping {} [{}] after {} failed attempts. {}",
+            document,
+            document_id,
+            MAX_EVENT_ATTEMPTS,
+            error
+        );
+        Ok(())
+    }
+
+    async fn handle_debit_note(&self, debit_note_id: &str) -> anyhow::Result<()> {
+        let debit_note = self.api.get_debit_note(debit_note_id).await?;
+
+        // Usage reported in debit note comes from Provider, so we check it
+        // against usage read from activity by ourselves.
+        match self
+            .activity_api
+            .state()
+            .get_usage(&debit_note.activity_id)
+            .await
+        {
+            Ok(usage) => {
+                if let Some(usage) = usage.current_usage {
+                    self.record_usage(&debit_note.agreement_id, &usage);
+                }
+            }
+            Err(e) => log::debug!(
+                "Can't read usage of activity [{}]. {}",
+                debit_note.activity_id,
+                e
+            ),
+        }
+
+        match self.check_debit_note(&debit_note) {
+            Ok(()) => {
+                self.api
+                    .accept_debit_note(
+                        &debit_note.debit_note_id,
+                        &self.acceptance(&debit_note.total_amount_due),
+                    )
+                    .await?;
+                self.accepted(&debit_note.agreement_id, &debit_note.total_amount_due);
+                log::debug!(
+                    "Accepted debit note [{}] for {}.",
+                    debit_note.debit_note_id,
+                    debit_note.total_amount_due
+                );
+            }
+            Err((reason, message)) => {
+                log::warn!(
+                    "Rejecting debit note [{}]: {}",
+                    debit_note.debit_note_id,
+                    message
+                );
+                self.api
+                    .reject_debit_note(&debit_note.debit_note_id, &rejection(reason, message))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn process_invoices(&self) -> anyhow::Result<()> {
+        let after = self.state.lock().unwrap().last_invoice_event;
+        let events = self
+            .api
+            .get_invoice_events(after.as_ref(), Some(Duration::from_secs(5)))
+            .await?;
+
+        for event in events {
+            if event.event_type == EventType::Received {
+                let handled = self.handle_invoice(&event.invoice_id).await;
+                self.check_attempt(&event.invoice_id, "invoice", handled)?;
+            }
+            self.state.lock().unwrap().last_invoice_event = Some(event.timestamp);
+        }
+        Ok(())
+    }
+
+    async fn handle_invoice(&self, invoice_id: &str) -> anyhow::Result<()> {
+        let invoice = self.api.get_invoice(invoice_id).await?;
+        match self.check_invoice(&invoice) {
+            Ok(()) => {
+                self.api
+                    .accept_invoice(&invoice.invoice_id, &self.acceptance(&invoice.amount))
+                    .await?;
+                self.accepted(&invoice.agreement_id, &invoice.amount);
+                if let Some(terms) = self
+                    .state
+                    .lock()
+                    .unwrap()
+                    .agreements
+                    .get_mut(&invoice.agreement_id)
+                {
+                    terms.invoiced = true;
+                }
+                log::info!(
+                    "Accepted invoice [{}] for {}. Spent {} of {}.",
+                    invoice.invoice_id,
+                    invoice.amount,
+                    self.spent(),
+                    self.budget
+                );
+            }
+            Err((reason, message)) => {
+                log::warn!("Rejecting invoice [{}]: {}", invoice.invoice_id, message);
+                self.api
+                    .reject_invoice(&invoice.invoice_id, &rejection(reason, message))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Debit note amount can't exceed price of the highest usage, that we read
+    /// from activity.
+    fn check_debit_note(&self, debit_note: &DebitNote) -> Result<(), (RejectionReason, String)> {
+        let state = self.state.lock().unwrap();
+        let terms = state
+            .agreements
+            .get(&debit_note.agreement_id)
+            .ok_or_else(|| {
+                (
+                    RejectionReason::UnsolicitedService,
+                    format!("unknown agreement [{}]", debit_note.agreement_id),
+                )
+            })?;
+        if terms.pricing.is_none() {
+            // We can't verify anything, so we trust allocation limit.
+            return Ok(());
+        }
+        self.check_amount(&debit_note.total_amount_due, terms.expected)
+    }
+
+    /// Invoice can't exceed cost of usage, that we saw in activity or accepted
+    /// in debit notes, whichever is higher.
+    fn check_invoice(&self, invoice: &Invoice) -> Result<(), (RejectionReason, String)> {
+        let state = self.state.lock().unwrap();
+        let terms = state.agreements.get(&invoice.agreement_id).ok_or_else(|| {
+            (
+                RejectionReason::UnsolicitedService,
+                format!("unknown agreement [{}]", invoice.agreement_id),
+            )
+        })?;
+
+        if terms.pricing.is_none() && terms.accepted.is_zero() {
+            // Nothing to compare with, so we trust allocation limit.
+            return Ok(());
+        }
+
+        let accepted = terms.accepted.to_f64().unwrap_or(0.0);
+        self.check_amount(&invoice.amount, terms.expected.max(accepted))
+    }
+
+    fn check_amount(
+        &self,
+        amount: &BigDecimal,
+        expected: f64,
+    ) -> Result<(), (RejectionReason, String)> {
+        let amount = amount.to_f64().unwrap_or(f64::INFINITY);
+        let limit = expected * (1.0 + self.tolerance);
+        if amount > limit {
+            return Err((
+                RejectionReason::IncorrectAmount,
+                format!(
+                    "amount {} exceeds {} expected from agreement",
+                    amount, expected
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn acceptance(&self, amount: &BigDecimal) -> Acceptance {
+        Acceptance {
+            total_amount_accepted: amount.clone(),
+            allocation_id: self.allocation.allocation_id.clone(),
+        }
+    }
+
+    fn accepted(&self, agreement_id: &str, amount: &BigDecimal) {
+        if let Some(terms) = self.state.lock().unwrap().agreements.get_mut(agreement_id) {
+            if *amount > terms.accepted {
+                terms.accepted = amount.clone();
+            }
+        }
+    }
+
+    fn all_invoiced(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.agreements.values().all(|terms| terms.invoiced)
+    }
+
+    /// Waits for invoices of all agreements and releases allocation.
+    pub async fn finish(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        while !self.all_invoiced() && Instant::now() < deadline {
+            tokio::time::delay_for(Duration::from_secs(1)).await;
+        }
+        if !self.all_invoiced() {
+            log::warn!("Not all Providers sent invoices in {}s.", timeout.as_secs());
+        }
+
+        log::info!(
+            "Releasing allocation [{}]. Spent {} of {}.",
+            self.allocation.allocation_id,
+            self.spent(),
+            self.budget
+        );
+        self.api
+            .release_allocation(&self.allocation.allocation_id)
+            .await
+            .map_err(|e| log::error!("Can't release allocation. Error: {}", e))
+            .ok();
+    }
+}
+
+fn rejection(reason: RejectionReason, message: String) -> Rejection {
+    Rejection {
+        rejection_reason: reason,
+        total_amount_accepted: BigDecimal::zero(),
+        message: Some(message),
+    }
+}
@@ -0,0 +1,508 @@
+use futures::future::{join, FutureExt, LocalBoxFuture};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+
+use crate::backend::{ProvingBackend, ReadyBackend};
+use crate::costs::CostLedger;
+use crate::payments::Payments;
+use crate::prover_runner::{
+    ask_for_block, fetch_job, prove_block, upload_job, BlockInfo, PreparedJob, ProverTimeout,
+};
+use crate::reputation::ReputationStore;
+use crate::resources::{ResourceGuard, Termination};
+use crate::zksync_client::ZksyncClient;
+
+/// Block requests from this period are used to compute hit rate.
+const HIT_RATE_WINDOW: Duration = Duration::from_secs(600);
+/// Pause between block requests, when server has nothing to prove.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// Interval of notifying server, that we still hold prefetched jobs.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// How often prefetching checks, if current proof is finished.
+const PREFETCH_TICK: Duration = Duration::from_secs(1);
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct ScaleArgs {
+    /// Providers kept, even if there are no blocks to prove.
+    #[structopt(long, env, default_value = "1")]
+    pub min_providers: usize,
+    /// Maximum number of Providers proving blocks at the same time.
+    #[structopt(long, env, default_value = "1")]
+    pub max_providers: usize,
+    /// Fraction of block requests returning block, above which pool grows.
+    #[structopt(long, env, default_value = "0.8")]
+    pub scale_up_hit_rate: f64,
+    /// Seconds, for which blocks must be waiting, before next Provider is added.
+    #[structopt(long, env, default_value = "120")]
+    pub scale_up_wait: u64,
+    /// Seconds without blocks to prove, after which idle Provider is released.
+    #[structopt(long, env, default_value = "600")]
+    pub idle_cooldown: u64,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct PrefetchArgs {
+    /// Blocks taken from server ahead, while Provider proves current block.
+    /// With 0 next block is taken only after current proof is published.
+    #[structopt(long, env, default_value = "0")]
+    pub prefetch_blocks: usize,
+    /// Upload prefetched blocks to Provider before current proof is finished.
+    /// ExeUnit runs commands one by one, so it helps only with local backend.
+    #[structopt(long, env)]
+    pub prefetch_upload: bool,
+}
+
+struct ScaleState {
+    polls: VecDeque<(Instant, bool)>,
+    /// Since when every block request returned block.
+    busy_since: Option<Instant>,
+    /// Workers proving blocks and backends being created.
+    workers: usize,
+    last_growth: Option<Instant>,
+    stopped: bool,
+}
+
+/// Decides about size of Providers pool based on how often zksync server
+/// has blocks for us and how long they are waiting.
+pub struct Autoscaler {
+    args: ScaleArgs,
+    state: Mutex<ScaleState>,
+}
+
+impl Autoscaler {
+    pub fn new(args: ScaleArgs) -> Autoscaler {
+        Autoscaler {
+            args,
+            state: Mutex::new(ScaleState {
+                polls: VecDeque::new(),
+                busy_since: None,
+                workers: 0,
+                last_growth: None,
+                stopped: false,
+            }),
+        }
+    }
+
+    pub fn record_poll(&self, found: bool) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.polls.push_back((now, found));
+        while let Some((time, _)) = state.polls.front() {
+            match now.duration_since(*time) > HIT_RATE_WINDOW {
+                true => state.polls.pop_front(),
+                false => break,
+            };
+        }
+        state.busy_since = match found {
+            true => state.busy_since.or(Some(now)),
+            false => None,
+        };
+    }
+
+    pub fn hit_rate(&self) -> Option<f64> {
+        let state = self.state.lock().unwrap();
+        let hits = state.polls.iter().filter(|(_, found)| *found).count();
+        match state.polls.len() {
+            0 => None,
+            polls => Some(hits as f64 / polls as f64),
+        }
+    }
+
+    /// Blocks are considered waiting, as long as no block request comes back
+    /// empty. Returns for how long.
+    pub fn wait_time(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        state
+            .busy_since
+            .map(|since| since.elapsed())
+            .unwrap_or_default()
+    }
+
+    /// Reserves place for new worker, if pool should grow.
+    pub fn try_grow(&self) -> bool {
+        let hit_rate = self.hit_rate().unwrap_or(0.0);
+        let wait_time = self.wait_time();
+        let scale_up_wait = Duration::from_secs(self.args.scale_up_wait);
+
+        let mut state = self.state.lock().unwrap();
+        if state.stopped || state.workers >= self.args.max_providers {
+            return false;
+        }
+
+        let below_min = state.workers < self.args.min_providers;
+        let piling_up = hit_rate >= self.args.scale_up_hit_rate
+            && wait_time >= scale_up_wait
+            && state
+                .last_growth
+                .map(|time| time.elapsed() >= scale_up_wait)
+                .unwrap_or(true);
+        if !below_min && !piling_up {
+            return false;
+        }
+
+        log::info!(
+            "Adding Provider to pool of {}. Hit rate: {:.2}, blocks waiting for {}s.",
+            state.workers,
+            hit_rate,
+            wait_time.as_secs()
+        );
+        state.workers += 1;
+        state.last_growth = Some(Instant::now());
+        true
+    }
+
+    /// Starts worker on backend created outside of autoscaler.
+    pub fn add_worker(&self) {
+        self.state.lock().unwrap().workers += 1;
+    }
+
+    pub fn remove_worker(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.workers = state.workers.saturating_sub(1);
+    }
+
+    /// Releases place of idle worker, unless pool would shrink below minimum.
+    pub fn try_release(&self, idle_since: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if idle_since.elapsed() < Duration::from_secs(self.args.idle_cooldown)
+            || state.workers <= self.args.min_providers
+        {
+            return false;
+        }
+        state.workers -= 1;
+        true
+    }
+
+    /// No more blocks will be taken.
+    pub fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.state.lock().unwrap().stopped
+    }
+}
+
+/// Proves blocks on all backends in pool.
+pub struct Worker<'a> {
+    pub zksync_client: Arc<ZksyncClient>,
+    pub ledger: &'a CostLedger,
+    pub reputation: &'a ReputationStore,
+    pub payments: Option<&'a Payments>,
+    pub autoscaler: &'a Autoscaler,
+    pub guard: &'a ResourceGuard,
+    pub prefetch: PrefetchArgs,
+    pub prover_timeout: Duration,
+    pub batch_job: bool,
+}
+
+impl<'a> Worker<'a> {
+    /// Checks if budget is enough for `jobs` more jobs.
+    fn can_afford(&self, jobs: usize) -> bool {
+        match self.payments {
+            Some(payments) => payments.can_take_block(self.ledger.max_cost() * jobs as f64),
+            None => true,
+        }
+    }
+
+    fn can_take_block(&self) -> bool {
+        if !self.can_afford(1) {
+            log::warn!("Budget exhausted. Not taking new blocks.");
+            self.autoscaler.stop();
+            return false;
+        }
+        !self.autoscaler.stopped()
+    }
+
+    async fn poll_block(&self, block_sizes: &[usize]) -> Option<BlockInfo> {
+        match ask_for_block(&self.zksync_client, block_sizes).await {
+            Ok(block) => {
+                self.autoscaler.record_poll(block.is_some());
+                block
+            }
+            Err(e) => {
+                log::warn!("{}", e);
+                None
+            }
+        }
+    }
+
+    /// Proves blocks on backend until it is released by autoscaler or budget
+    /// is exhausted. Returns backend to be destroyed.
+    async fn prove_blocks(
+        &self,
+        ready: ReadyBackend,
+        mut next_block: Option<BlockInfo>,
+    ) -> Arc<dyn ProvingBackend> {
+        let ReadyBackend {
+            backend,
+            block_sizes,
+        } = ready;
+        let prefetched = RefCell::new(VecDeque::new());
+        let mut idle_since = Instant::now();
+        while self.can_take_block() {
+            let queued = prefetched.borrow_mut().pop_front();
+            let job = match queued {
+                Some(job) => job,
+                None => {
+                    let block = match next_block.take() {
+                        Some(block) => block,
+                        None => match self.poll_block(&block_sizes).await {
+                            Some(block) => block,
+                            None => {
+                                if self.autoscaler.try_release(idle_since) {
+                                    log::info!(
+                                        "No blocks to prove for {}s. Releasing {}.",
+                                        idle_since.elapsed().as_secs(),
+                                        backend.name()
+                                    );
+                                    break;
+                                }
+                                tokio::time::delay_for(POLL_INTERVAL).await;
+                                continue;
+                            }
+                        },
+                    };
+                    match fetch_job(&self.zksync_client, block).await {
+                        Ok(job) => job,
+                        Err(e) => {
+                            log::warn!("{}", e);
+                            tokio::time::delay_for(POLL_INTERVAL).await;
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            // Prefetching isn't dropped in the middle, when proof is finished, because
+            // block assigned to us by server would be lost until server timeout.
+            let proved = Cell::new(false);
+            let proving = async {
+                let result = prove_block(
+                    self.zksync_client.clone(),
+                    backend.clone(),
+                    job,
+                    self.ledger,
+                    self.reputation,
+                    self.prover_timeout,
+                    self.batch_job,
+                )
+                .await;
+                proved.set(true);
+                result
+            };
+            let prefetching = self.prefetch(backend.as_ref(), &block_sizes, &prefetched, &proved);
+            let (result, ()) = join(proving, prefetching).await;
+
+            if let Some(payments) = self.payments {
+                payments.track_usage(backend.as_ref()).await;
+            }
+            if let Err(e) = result {
+                log::warn!("{}", e);
+                if e.downcast_ref::<ProverTimeout>().is_some() {
+                    // Autoscaler will replace backend, if pool is below minimum.
+                    log::warn!("Releasing {} with hanging prover.", backend.name());
+                    self.autoscaler.remove_worker();
+                    break;
+                }
+                tokio::time::delay_for(POLL_INTERVAL).await;
+            }
+            idle_since = Instant::now();
+        }
+
+        // Server would wait for heartbeat timeout, before giving these blocks
+        // to other provers, so we release them explicitly.
+        let abandoned = prefetched.into_inner();
+        if !abandoned.is_empty() {
+            log::info!("Releasing {} prefetched blocks.", abandoned.len());
+        }
+        for job in abandoned {
+            let job_id = job.block.job_id;
+            self.zksync_client
+                .prover_stopped(job_id)
+                .await
+                .map_err(|e| log::warn!("Failed to release job '{}'. {}", job_id, e))
+                .ok();
+        }
+        backend
+    }
+
+    /// Takes next blocks from server and downloads their data, until current block
+    /// is `proved`. Server assigns jobs to us, when we ask for block, so prefetched
+    /// jobs are kept alive with heartbeats until prover gets to them. Block, that was
+    /// taken, is always downloaded before returning.
+    async fn prefetch(
+        &self,
+        backend: &dyn ProvingBackend,
+        block_sizes: &[usize],
+        prefetched: &RefCell<VecDeque<PreparedJob>>,
+        proved: &Cell<bool>,
+    ) {
+        if self.prefetch.prefetch_blocks == 0 {
+            return;
+        }
+
+        while !proved.get() {
+            while !proved.get() {
+                let held = prefetched.borrow().len();
+                // Current job and prefetched ones must fit in budget.
+                if held >= self.prefetch.prefetch_blocks
+                    || self.autoscaler.stopped()
+                    || !self.can_afford(held + 2)
+                {
+                    break;
+                }
+
+                let block = match self.poll_block(block_sizes).await {
+                    Some(block) => block,
+                    None => break,
+                };
+                let mut job = match fetch_job(&self.zksync_client, block).await {
+                    Ok(job) => job,
+                    Err(e) => {
+                        log::warn!("Failed to prefetch block. {}", e);
+                        break;
+                    }
+                };
+                if self.prefetch.prefetch_upload {
+                    upload_job(backend, &mut job)
+                        .await
+                        .map_err(|e| log::warn!("Failed to upload prefetched block. {}", e))
+                        .ok();
+                }
+                prefetched.borrow_mut().push_back(job);
+            }
+
+            let heartbeat = Instant::now() + HEARTBEAT_INTERVAL;
+            while !proved.get() && Instant::now() < heartbeat {
+                tokio::time::delay_for(PREFETCH_TICK).await;
+            }
+            if proved.get() {
+                break;
+            }
+
+            let jobs = prefetched
+                .borrow()
+                .iter()
+                .map(|job| job.block.job_id)
+                .collect::<Vec<_>>();
+            for job_id in jobs {
+                self.zksync_client
+                    .working_on(job_id)
+                    .await
+                    .map_err(|e| log::warn!("Heartbeat for job '{}' failed. {}", job_id, e))
+                    .ok();
+            }
+        }
+    }
+}
+
+enum PoolEvent {
+    Created(anyhow::Result<ReadyBackend>, Option<BlockInfo>),
+    Released(Arc<dyn ProvingBackend>),
+}
+
+/// Proves blocks starting with `first` backend and scales pool, until autoscaler
+/// is stopped. Backends created by `create_backend` must be registered in resource
+/// guard, so they are released, when proving is interrupted.
+pub async fn run_pool<'a, F>(first: ReadyBackend, worker: &'a Worker<'a>, create_backend: F)
+where
+    F: Fn() -> LocalBoxFuture<'a, anyhow::Result<ReadyBackend>>,
+{
+    let autoscaler = worker.autoscaler;
+    let mut tasks = FuturesUnordered::new();
+    // Without backends in pool, we ask for blocks, that last backend could prove.
+    let mut block_sizes = first.block_sizes.clone();
+
+    autoscaler.add_worker();
+    tasks.push(
+        worker
+            .prove_blocks(first, None)
+            .map(PoolEvent::Released)
+            .boxed_local(),
+    );
+
+    loop {
+        if autoscaler.try_grow() {
+            tasks.push(
+                create_backend()
+                    .map(|backend| PoolEvent::Created(backend, None))
+                    .boxed_local(),
+            );
+        }
+
+        // All Providers were released, so we ask for blocks ourselves
+        // and create new backend, when some block appears.
+        if tasks.is_empty() {
+            if !worker.can_take_block() {
+                break;
+            }
+            match worker.poll_block(&block_sizes).await {
+                Some(block) => {
+                    autoscaler.add_worker();
+                    tasks.push(
+                        create_backend()
+                            .map(|backend| PoolEvent::Created(backend, Some(block)))
+                            .boxed_local(),
+                    );
+                }
+                None => tokio::time::delay_for(POLL_INTERVAL).await,
+            }
+            continue;
+        }
+
+        let event = match tokio::time::timeout(POLL_INTERVAL, tasks.next()).await {
+            Ok(Some(event)) => event,
+            Ok(None) | Err(_) => continue,
+        };
+
+        match event {
+            PoolEvent::Created(Ok(ready), block) => {
+                log::info!("Added {} to pool.", ready.backend.name());
+                let block = block.filter(|block| {
+                    let supported = ready.block_sizes.contains(&block.block_size);
+                    if !supported {
+                        log::warn!(
+                            "{} can't prove block '{}' of size {}. Server will assign it to other prover.",
+                            ready.backend.name(),
+                            block.block_id,
+                            block.block_size
+                        );
+                    }
+                    supported
+                });
+                block_sizes = ready.block_sizes.clone();
+                tasks.push(
+                    worker
+                        .prove_blocks(ready, block)
+                        .map(PoolEvent::Released)
+                        .boxed_local(),
+                );
+            }
+            PoolEvent::Created(Err(e), block) => {
+                autoscaler.remove_worker();
+                match block {
+                    Some(block) => log::warn!(
+                        "Can't create backend for block '{}'. Error: {}",
+                        block.block_id,
+                        e
+                    ),
+                    None => log::warn!("Can't add Provider to pool. Error: {}", e),
+                }
+            }
+            PoolEvent::Released(backend) => {
+                if let Some(payments) = worker.payments {
+                    payments.track_usage(backend.as_ref()).await;
+                }
+                worker
+                    .guard
+                    .release_backend(&backend, &Termination::Finished)
+                    .await
+            }
+        }
+    }
+}
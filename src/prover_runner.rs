@@ -1,19 +1,27 @@
 use anyhow::{anyhow, bail};
-use futures::future::ready;
+use chrono::Utc;
 use futures::StreamExt;
 use indicatif::ProgressBar;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
 
-use crate::zksync_client::ZksyncClient;
-use ya_client_model::activity::{CommandOutput, RuntimeEventKind};
-use yarapi::rest::activity::DefaultActivity;
-use yarapi::rest::streaming::{ResultStream, StreamingActivity};
-use yarapi::rest::Transfers;
+use crate::backend::{CommandResult, JobCommand, ProverEvent, ProvingBackend};
+use crate::costs::{estimate, CostLedger, JobCost};
+use crate::reputation::{Outcome, ReputationStore};
+use crate::zksync_client::{ZksyncClient, ZksyncClientError};
+use prover_common::artifact::write_json_atomic;
+use prover_common::capabilities::{CapabilityReport, CAPABILITIES_FILE};
+use prover_common::manifest::{manifest_path, JobStatus, ResultManifest};
+use prover_common::network::{NetworkConfig, NETWORK_CONFIG_FILE};
 use zksync_crypto::proof::EncodedProofPlonk;
+use zksync_prover_utils::prover_data::ProverData;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct BlockInfo {
@@ -22,12 +30,18 @@ pub struct BlockInfo {
     pub block_size: usize,
 }
 
-pub async fn prove_block(
-    zksync_client: Arc<ZksyncClient>,
-    activity: Arc<DefaultActivity>,
-) -> anyhow::Result<()> {
-    let block = ask_for_block(zksync_client.clone()).await?;
+/// Block with prover data downloaded from server, waiting for prover.
+pub struct PreparedJob {
+    pub block: BlockInfo,
+    data: ProverData,
+    uploaded: bool,
+}
 
+/// Downloads data needed to prove block.
+pub async fn fetch_job(
+    zksync_client: &ZksyncClient,
+    block: BlockInfo,
+) -> anyhow::Result<PreparedJob> {
     log::info!(
         "Got block '{}' of size '{}' to prove. Job id: '{}'.",
         &block.block_id,
@@ -35,20 +49,11 @@ pub async fn prove_block(
         &block.job_id
     );
 
-    activity
-        .send_json(&PathBuf::from_str("/blocks/job-info.json")?, &block)
-        .await
-        .map_err(|e| anyhow!("Transferring block info: {}", e))?;
-
     // TODO: Save job info on disk for debugging.
     fs::create_dir_all("blocks")?;
     let job_file = PathBuf::from(format!("blocks/job-info-{}.json", block.job_id));
     save(&job_file, &block).map_err(|e| anyhow!("Failed to debug job info. {}", e))?;
 
-    // This line will set last job info parameters in blocks directory. You can run docker container locally
-    // in workdir and it should work the same as on provider.
-    fs::copy(&job_file, "blocks/job-info.json").ok();
-
     // TODO: Modify zksync to return ProverData here.
     // TODO: We shouldn't download block here. Generate address and command ExeUnit to download this data.
     let data = zksync_client
@@ -69,15 +74,195 @@ pub async fn prove_block(
     )
     .map_err(|e| anyhow!("Failed to debug save block. {}", e))?;
 
+    log::info!("Downloaded prover data for block '{}'.", block.block_id);
+    Ok(PreparedJob {
+        block,
+        data,
+        uploaded: false,
+    })
+}
+
+/// Uploads prover data. Each block has separate file, so data can be uploaded,
+/// while prover is still computing previous block.
+pub async fn upload_job(backend: &dyn ProvingBackend, job: &mut PreparedJob) -> anyhow::Result<()> {
+    if job.uploaded {
+        return Ok(());
+    }
+
     // TODO: Remove downloading in future. Provider ExeUnit will do it.
-    log::info!("Downloaded prover data. Uploading data to Provider...");
-    let block_remote_path = PathBuf::from(format!("/blocks/block-{}.json", block.block_id));
-    activity.send_json(&block_remote_path, &data).await?;
+    log::info!(
+        "Uploading data of block '{}' to {}...",
+        job.block.block_id,
+        backend.name()
+    );
+    let block_remote_path = PathBuf::from(format!("/blocks/block-{}.json", job.block.block_id));
+    backend.send_json(&block_remote_path, &job.data).await?;
+    job.uploaded = true;
+    Ok(())
+}
 
-    log::info!("Block uploaded. Running prover on remote yagna node...");
-    run_yagna_prover(activity.clone())
-        .await
-        .map_err(|e| anyhow!("Failed to run prover on remote node. Error: {}", e))?;
+/// Prover may still be running on backend after timeout, so backend
+/// can't be used for next blocks.
+#[derive(Debug, thiserror::Error)]
+#[error("Prover on {backend} didn't finish in {}s.", .timeout.as_secs())]
+pub struct ProverTimeout {
+    pub backend: String,
+    pub timeout: Duration,
+}
+
+pub async fn prove_block(
+    zksync_client: Arc<ZksyncClient>,
+    backend: Arc<dyn ProvingBackend>,
+    mut job: PreparedJob,
+    ledger: &CostLedger,
+    reputation: &ReputationStore,
+    prover_timeout: Duration,
+    batch: bool,
+) -> anyhow::Result<()> {
+    let block = job.block.clone();
+    let manifest_path = manifest_path(Path::new("/proofs"), block.block_id);
+    let proof_path = PathBuf::from(format!("/proofs/proof-{}.json", &block.block_id));
+
+    // Last job info of every backend. Copy it to blocks directory to run docker container locally
+    // in workdir and it should work the same as on provider.
+    save(&debug_dir(backend.as_ref()).join("job-info.json"), &block).ok();
+
+    let mut downloaded = HashMap::new();
+    let usage_before;
+    let result = match batch {
+        false => {
+            upload_job(backend.as_ref(), &mut job).await?;
+
+            // Prover reads single job info file, so it can be sent only right before run.
+            backend
+                .send_json(&PathBuf::from_str("/blocks/job-info.json")?, &block)
+                .await
+                .map_err(|e| anyhow!("Transferring block info: {}", e))?;
+
+            log::info!("Block uploaded. Running prover on {}...", backend.name());
+            usage_before = read_usage(backend.as_ref()).await;
+            tokio::time::timeout(
+                prover_timeout,
+                run_prover(backend.as_ref(), vec!["ya-prover".to_string()]),
+            )
+            .await
+        }
+        true => {
+            log::info!("Running job script on {}...", backend.name());
+            usage_before = read_usage(backend.as_ref()).await;
+            let downloads = vec![manifest_path.clone(), proof_path.clone()];
+            tokio::time::timeout(
+                prover_timeout,
+                run_job_script(backend.as_ref(), &job, downloads, &mut downloaded),
+            )
+            .await
+        }
+    };
+    let usage_after = read_usage(backend.as_ref()).await;
+    let provider = backend.provider();
+
+    // Provider charges us, even if prover failed.
+    let (duration_sec, cpu_sec, cost) = estimate(
+        backend.pricing(),
+        usage_before.as_deref(),
+        usage_after.as_deref(),
+    );
+    let job_cost = JobCost {
+        block_id: block.block_id,
+        job_id: block.job_id,
+        block_size: block.block_size,
+        provider: provider.clone(),
+        subnet: backend.subnet(),
+        finished_at: Utc::now(),
+        return_code: match &result {
+            Ok(Ok(return_code)) => *return_code,
+            _ => None,
+        },
+        duration_sec,
+        cpu_sec,
+        cost,
+    };
+    log::info!(
+        "Block '{}' on [{}] in subnet {} estimated cost: {:?}, duration: {:?}s, cpu time: {:?}s.",
+        block.block_id,
+        job_cost.provider,
+        job_cost.subnet.as_deref().unwrap_or("-"),
+        job_cost.cost,
+        job_cost.duration_sec,
+        job_cost.cpu_sec
+    );
+    ledger
+        .record(&job_cost)
+        .map_err(|e| log::warn!("Failed to record cost of job '{}'. {}", block.job_id, e))
+        .ok();
+
+    let return_code = match result {
+        Ok(Ok(return_code)) => return_code,
+        Ok(Err(e)) => {
+            reputation.record(&provider, Outcome::Failed);
+            bail!("Failed to run prover on {}. Error: {}", backend.name(), e)
+        }
+        Err(_) => {
+            reputation.record(&provider, Outcome::Timeout);
+            return Err(ProverTimeout {
+                backend: backend.name(),
+                timeout: prover_timeout,
+            }
+            .into());
+        }
+    };
+
+    // Manifest tells us, if we should expect proof at all. Batch stops after failed
+    // prover, so manifest must be downloaded separately then.
+    let manifest: ResultManifest = match downloaded.remove(&manifest_path) {
+        Some(manifest) => serde_json::from_value(manifest).map_err(anyhow::Error::from),
+        None => backend.download_json(&manifest_path).await,
+    }
+    .map_err(|e| {
+        reputation.record(&provider, Outcome::Failed);
+        anyhow!(
+            "Prover exited with code {:?} without result manifest for block '{}'. Error: {}",
+            return_code,
+            block.block_id,
+            e
+        )
+    })?;
+
+    fs::create_dir_all("proofs").ok();
+    save(
+        &PathBuf::from(format!("proofs/result-{}.json", &block.block_id)),
+        &manifest,
+    )
+    .map_err(|e| log::warn!("Failed to debug save result manifest. {}", e))
+    .ok();
+
+    match manifest.status {
+        JobStatus::Success => log::info!(
+            "Prover {} finished block '{}'. Timings: {:?}, peak memory: {:?} KiB.",
+            manifest.prover_version,
+            block.block_id,
+            manifest.timings,
+            manifest.peak_memory_kib
+        ),
+        JobStatus::InvalidProof => {
+            reputation.record(&provider, Outcome::InvalidProof);
+            bail!(
+                "Proof for block '{}' computed on {} didn't pass verification: {}",
+                block.block_id,
+                backend.name(),
+                manifest.describe_error()
+            )
+        }
+        JobStatus::Failed => {
+            reputation.record(&provider, Outcome::Failed);
+            bail!(
+                "Prover on {} failed to prove block '{}': {}",
+                backend.name(),
+                block.block_id,
+                manifest.describe_error()
+            )
+        }
+    }
 
     // Notify server, that we are computing proof for block.
     zksync_client.working_on(block.job_id).await.map_err(|e| {
@@ -90,12 +275,32 @@ pub async fn prove_block(
 
     log::info!("Proof for block generated. Downloading...");
 
-    let proof_path = PathBuf::from(format!("/proofs/proof-{}.json", &block.block_id));
-    let verified_proof: EncodedProofPlonk = activity.download_json(&proof_path).await?;
+    let proof = match downloaded.remove(&proof_path) {
+        Some(proof) => serde_json::from_value(proof).map_err(anyhow::Error::from),
+        None => backend.download_json(&proof_path).await,
+    };
+    let verified_proof: EncodedProofPlonk = match proof {
+        Ok(proof) => proof,
+        Err(e) => {
+            reputation.record(&provider, Outcome::Failed);
+            return Err(e);
+        }
+    };
+    reputation.record(
+        &provider,
+        Outcome::Completed {
+            block_size: block.block_size,
+            duration_sec: manifest
+                .timings
+                .total_ms
+                .map(|ms| ms as f64 / 1000.0)
+                .or(job_cost.duration_sec)
+                .unwrap_or_default(),
+        },
+    );
 
     log::info!("Proof downloaded. Publishing proof on server...");
 
-    fs::create_dir_all("proofs").ok();
     save(
         &PathBuf::from(format!("proofs/proof-{}.json", &block.block_id)),
         &verified_proof,
@@ -103,39 +308,41 @@ pub async fn prove_block(
     .map_err(|e| log::warn!("Failed to debug save proof. {}", e))
     .ok();
 
-    zksync_client
-        .publish(block.block_id, verified_proof)
-        .await
-        .map_err(|e| {
-            anyhow!(
-                "Failed to publish proof for block '{}' and job '{}'. Error: {}",
-                block.block_id,
-                block.job_id,
-                e
-            )
-        })?;
-
-    log::info!("Block '{}' published.", block.block_id);
+    match zksync_client.publish(block.block_id, verified_proof).await {
+        Ok(()) => log::info!("Block '{}' published.", block.block_id),
+        // Other prover was faster. Our work is wasted, but it's not an error.
+        Err(ZksyncClientError::ProofAlreadyExists(_)) => log::warn!(
+            "Proof for block '{}' was already published by someone else.",
+            block.block_id
+        ),
+        Err(e) => bail!(
+            "Failed to publish proof for block '{}' and job '{}'. Error: {}",
+            block.block_id,
+            block.job_id,
+            e
+        ),
+    }
     Ok(())
 }
 
-async fn ask_for_block(zksync_client: Arc<ZksyncClient>) -> anyhow::Result<BlockInfo> {
-    // TODO: Make configurable
-    let supported_sizes: Vec<usize> = vec![6, 30, 74, 150, 320, 630];
-
+/// Returns `None`, if server has no blocks of `block_sizes` to prove.
+pub async fn ask_for_block(
+    zksync_client: &ZksyncClient,
+    block_sizes: &[usize],
+) -> anyhow::Result<Option<BlockInfo>> {
     // Try ask server for different sizes of blocks.
-    for block_size in supported_sizes.iter().cloned() {
+    for block_size in block_sizes.iter().cloned() {
         let info = zksync_client
             .block_to_prove(block_size)
             .await
             .map_err(|e| anyhow!("Failed to download block to prove. Error: {}", e))?;
 
         if let Some((block_id, job_id)) = info {
-            return Ok(BlockInfo {
+            return Ok(Some(BlockInfo {
                 block_id,
                 block_size,
                 job_id,
-            });
+            }));
         } else {
             log::debug!(
                 "Block of size {} not found. Checking other possible sizes",
@@ -143,77 +350,587 @@ async fn ask_for_block(zksync_client: Arc<ZksyncClient>) -> anyhow::Result<Block
             );
         }
     }
-    bail!("Checked all possible block sizes and didn't find any.")
+    log::debug!("Checked all possible block sizes and didn't find any.");
+    Ok(None)
 }
 
-async fn run_yagna_prover(activity: Arc<DefaultActivity>) -> anyhow::Result<()> {
-    let bar_max: u64 = 1644;
-    let bar = ProgressBar::new(bar_max);
+/// Runs prover in network mode. Prover asks zksync server for blocks and publishes
+/// proofs by itself, so we only watch its execution. Returns prover exit code, when it exits.
+pub async fn supervise_prover(
+    backend: Arc<dyn ProvingBackend>,
+    server_api_url: &Url,
+    worker_name: &str,
+    config: &NetworkConfig,
+) -> anyhow::Result<Option<i32>> {
+    log::info!(
+        "Running prover in network mode on {}. Worker name: '{}'.",
+        backend.name(),
+        worker_name
+    );
 
-    bar.inc(0);
+    backend
+        .send_json(&PathBuf::from("/blocks").join(NETWORK_CONFIG_FILE), config)
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "Failed to send network config to {}. Error: {}",
+                backend.name(),
+                e
+            )
+        })?;
 
-    let batch = activity
-        .run_streaming("/bin/yagna-prover", vec!["ya-prover".to_string()])
-        .await?
-        .debug(".debug")?;
-    batch
-        .stream()
-        .await?
-        .forward_to_file(
-            &PathBuf::from("stdout-output.txt"),
-            &PathBuf::from("stderr-output.txt"),
-        )?
-        .inspect(|event| match &event.kind {
-            RuntimeEventKind::StdOut(output) => bar.inc(match output {
-                CommandOutput::Str(text) => text.len(),
-                CommandOutput::Bin(vec) => vec.len(),
-            } as u64),
-            _ => (),
-        })
-        .take_while(|event| {
-            ready(match &event.kind {
-                RuntimeEventKind::Finished {
-                    return_code,
-                    message,
-                } => {
-                    let no_msg = "".to_string();
-                    log::info!(
-                        "ExeUnit finished proving with code {}, and message: {}",
-                        return_code,
-                        message.as_ref().unwrap_or(&no_msg)
-                    );
-                    false
-                }
-                _ => true,
-            })
+    let args = vec![
+        worker_name.to_string(),
+        "--mode".to_string(),
+        "network".to_string(),
+        "--server-url".to_string(),
+        server_api_url.to_string(),
+    ];
+    run_prover(backend.as_ref(), args)
+        .await
+        .map_err(|e| anyhow!("Failed to run prover on {}. Error: {}", backend.name(), e))
+}
+
+/// Runs prover in `check-keys` mode and collects block sizes, it can prove.
+pub async fn check_capabilities(
+    backend: Arc<dyn ProvingBackend>,
+) -> anyhow::Result<CapabilityReport> {
+    let args = vec!["ya-prover", "--mode", "check-keys"];
+    let return_code = wait_for_prover(
+        backend.as_ref(),
+        args.into_iter().map(str::to_string).collect(),
+    )
+    .await?;
+    log::debug!("Keys check finished with code {}.", return_code);
+
+    // Report is written even if some keys are missing.
+    let report: CapabilityReport = backend
+        .download_json(&Path::new("/proofs").join(CAPABILITIES_FILE))
+        .await
+        .map_err(|e| anyhow!("Can't download capability report. Error: {}", e))?;
+
+    save(&PathBuf::from(CAPABILITIES_FILE), &report)
+        .map_err(|e| log::warn!("Failed to debug save capability report. {}", e))
+        .ok();
+
+    log::info!(
+        "Prover {} on {} supports block sizes: {:?}.",
+        report.prover_version,
+        backend.name(),
+        report.ready_sizes
+    );
+    for size in report.sizes.iter().filter(|size| !size.ready) {
+        log::warn!(
+            "Block size {} not supported on {}. Verification key: {:?}, setup: {:?}.",
+            size.block_size,
+            backend.name(),
+            size.verification_key.status,
+            size.setup.as_ref().map(|setup| setup.status)
+        );
+    }
+    Ok(report)
+}
+
+async fn read_usage(backend: &dyn ProvingBackend) -> Option<Vec<f64>> {
+    backend
+        .usage()
+        .await
+        .map_err(|e| log::warn!("Can't read usage of {}. {}", backend.name(), e))
+        .ok()
+        .flatten()
+}
+
+/// Runs prover without progress reporting and waits for it's exit code.
+pub async fn wait_for_prover(
+    backend: &dyn ProvingBackend,
+    args: Vec<String>,
+) -> anyhow::Result<i32> {
+    let mut events = backend.run(args).await?;
+    while let Some(event) = events.next().await {
+        match event {
+            ProverEvent::StdErr(output) => log::debug!("{}", output.trim_end()),
+            ProverEvent::StdOut(_) => (),
+            ProverEvent::Finished { return_code, .. } => return Ok(return_code),
+            ProverEvent::Failed(e) => bail!("Prover execution failed. Error: {}", e),
+        }
+    }
+    bail!("Prover events stream ended before prover finished.")
+}
+
+/// Returns prover exit code, if it finished.
+async fn run_prover(
+    backend: &dyn ProvingBackend,
+    args: Vec<String>,
+) -> anyhow::Result<Option<i32>> {
+    let mut output = ProverOutput::new(backend)?;
+
+    let mut exit_code = None;
+    let mut events = backend.run(args).await?;
+    while let Some(event) = events.next().await {
+        match event {
+            ProverEvent::Finished { return_code, .. } => {
+                output.write(event)?;
+                exit_code = Some(return_code);
+                break;
+            }
+            ProverEvent::Failed(e) => {
+                output.finish();
+                bail!("Prover execution failed. Error: {}", e)
+            }
+            event => output.write(event)?,
+        }
+    }
+
+    output.finish();
+    Ok(exit_code)
+}
+
+/// Runs whole job as single script: uploads block, runs prover and downloads
+/// result files, which are put to `downloaded`. Returns prover exit code.
+async fn run_job_script(
+    backend: &dyn ProvingBackend,
+    job: &PreparedJob,
+    downloads: Vec<PathBuf>,
+    downloaded: &mut HashMap<PathBuf, serde_json::Value>,
+) -> anyhow::Result<Option<i32>> {
+    let mut commands = vec![];
+    if !job.uploaded {
+        commands.push(JobCommand::Upload {
+            path: PathBuf::from(format!("/blocks/block-{}.json", job.block.block_id)),
+            data: serde_json::to_value(&job.data)?,
+        });
+    }
+    commands.push(JobCommand::Upload {
+        path: PathBuf::from("/blocks/job-info.json"),
+        data: serde_json::to_value(&job.block)?,
+    });
+    commands.push(JobCommand::Run {
+        args: vec!["ya-prover".to_string()],
+    });
+    commands.extend(
+        downloads
+            .into_iter()
+            .map(|path| JobCommand::Download { path }),
+    );
+
+    let mut output = ProverOutput::new(backend)?;
+    let mut write_error = None;
+    let results = backend
+        .run_script(commands.clone(), &mut |event| {
+            if let Err(e) = output.write(event) {
+                write_error.get_or_insert(e);
+            }
         })
-        .for_each(|_| ready(()))
         .await;
-    batch.wait_for_finish().await?;
+    output.finish();
+    let results = results?;
+    if let Some(e) = write_error {
+        log::warn!("Failed to save prover output. {}", e);
+    }
 
-    bar.set_position(bar_max);
-    bar.finish_and_clear();
-    Ok(())
+    let mut exit_code = None;
+    for (idx, (command, result)) in commands.into_iter().zip(results).enumerate() {
+        log::debug!(
+            "Block '{}' job step {} [{}]: {:?}",
+            job.block.block_id,
+            idx,
+            command,
+            result
+        );
+        match (command, result) {
+            (JobCommand::Run { .. }, CommandResult::Exited(return_code)) => {
+                exit_code = Some(return_code)
+            }
+            (JobCommand::Download { path }, CommandResult::Downloaded(value)) => {
+                downloaded.insert(path, value);
+            }
+            // Downloads are retried separately, when needed.
+            (JobCommand::Download { path }, result) => log::debug!(
+                "Step {} [download {}] of job script didn't succeed: {:?}",
+                idx,
+                path.display(),
+                result
+            ),
+            (command, CommandResult::Failed(e)) => {
+                bail!("Step {} [{}] of job script failed. {}", idx, command, e)
+            }
+            (command, CommandResult::Skipped) => {
+                bail!("Step {} [{}] of job script wasn't executed.", idx, command)
+            }
+            _ => (),
+        }
+    }
+    Ok(exit_code)
+}
+
+/// Shows prover progress and saves its output to files.
+struct ProverOutput {
+    bar: ProgressBar,
+    stdout: fs::File,
+    stderr: fs::File,
+}
+
+impl ProverOutput {
+    const BAR_MAX: u64 = 1644;
+
+    fn new(backend: &dyn ProvingBackend) -> anyhow::Result<ProverOutput> {
+        let bar = ProgressBar::new(Self::BAR_MAX);
+        bar.inc(0);
+
+        let dir = debug_dir(backend);
+        Ok(ProverOutput {
+            bar,
+            stdout: fs::File::create(dir.join("stdout-output.txt"))?,
+            stderr: fs::File::create(dir.join("stderr-output.txt"))?,
+        })
+    }
+
+    fn write(&mut self, event: ProverEvent) -> std::io::Result<()> {
+        match event {
+            ProverEvent::StdOut(output) => {
+                self.bar.inc(output.len() as u64);
+                self.stdout.write_all(output.as_bytes())?;
+            }
+            ProverEvent::StdErr(output) => self.stderr.write_all(output.as_bytes())?,
+            ProverEvent::Finished {
+                return_code,
+                message,
+            } => log::info!(
+                "ExeUnit finished proving with code {}, and message: {}",
+                return_code,
+                message.unwrap_or_default()
+            ),
+            ProverEvent::Failed(_) => (),
+        }
+        Ok(())
+    }
+
+    fn finish(&self) {
+        self.bar.set_position(Self::BAR_MAX);
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Directory for debug files of single backend, so workers in pool don't
+/// overwrite each other's files.
+fn debug_dir(backend: &dyn ProvingBackend) -> PathBuf {
+    let key = backend.agreement_id().unwrap_or_else(|| backend.provider());
+    let dir = PathBuf::from("backends").join(key);
+    fs::create_dir_all(&dir)
+        .map_err(|e| log::warn!("Can't create [{}]. {}", dir.display(), e))
+        .ok();
+    dir
 }
 
 // Saving blocks for debugging.
 fn save<T: Sized + Serialize>(data_path: &Path, data: &T) -> anyhow::Result<()> {
-    use std::fs::File;
+    write_json_atomic(data_path, data)
+}
 
-    let file = File::create(&data_path).map_err(|e| {
-        anyhow!(
-            "Can't open data file [{}]. Error: {}",
-            data_path.display(),
-            e
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Auth;
+    use crate::backend::fake::FakeBackend;
+    use crate::backend::local::LocalBackend;
+    use crate::testing::{client, fast_retry, ledger, reputation, workdir};
+    use mock_prover_server::{empty_prover_data, MockServer};
+    use prover_common::manifest::PhaseTimings;
+
+    fn manifest(block: &BlockInfo, status: JobStatus) -> ResultManifest {
+        ResultManifest {
+            block_id: block.block_id,
+            job_id: block.job_id,
+            block_size: block.block_size,
+            status,
+            error: None,
+            error_class: None,
+            timings: PhaseTimings::default(),
+            peak_memory_kib: None,
+            prover_version: "test".to_string(),
+            key_dir: None,
+        }
+    }
+
+    /// Script standing in for yagna-prover. Checks, that job was uploaded
+    /// and copies prepared results to proofs directory.
+    #[cfg(unix)]
+    fn fake_prover(name: &str, block: &BlockInfo, status: JobStatus) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = workdir().join(name);
+        fs::create_dir_all(&dir).unwrap();
+        save(&dir.join("result.json"), &manifest(block, status)).unwrap();
+        save(&dir.join("proof.json"), &EncodedProofPlonk::default()).unwrap();
+
+        let script = dir.join("yagna-prover");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\n\
+                 test -f \"$BLOCKS_DIR/job-info.json\" || exit 1\n\
+                 test -f \"$BLOCKS_DIR/block-{block}.json\" || exit 1\n\
+                 echo \"proving block {block}\"\n\
+                 cp {dir}/result.json \"$PROOFS_DIR/result-{block}.json\"\n\
+                 cp {dir}/proof.json \"$PROOFS_DIR/proof-{block}.json\"\n",
+                block = block.block_id,
+                dir = dir.display()
+            ),
         )
-    })?;
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        script
+    }
 
-    serde_json::to_writer(file, &data).map_err(|e| {
-        anyhow!(
-            "Failed to serialize data to file: {}. Error: {}",
-            data_path.display(),
-            e
+    #[actix_rt::test]
+    async fn only_ready_sizes_are_requested() {
+        let server = MockServer::start(None).unwrap();
+        server.add_block(13, 30, empty_prover_data());
+        let zksync_client = client(&server, Auth::None, fast_retry(0));
+
+        let block = ask_for_block(&zksync_client, &[6]).await.unwrap();
+        assert!(block.is_none());
+
+        let block = ask_for_block(&zksync_client, &[6, 30])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((block.block_id, block.block_size), (13, 30));
+    }
+
+    #[cfg(unix)]
+    #[actix_rt::test]
+    async fn local_prover_proof_is_published() {
+        let server = MockServer::start(None).unwrap();
+        let job_id = server.add_block(11, 6, empty_prover_data());
+        let zksync_client = client(&server, Auth::None, fast_retry(2));
+        let ledger = ledger("local-published");
+        let reputation = reputation("local-published");
+
+        let block = ask_for_block(&zksync_client, &[6]).await.unwrap().unwrap();
+        assert_eq!((block.block_id, block.job_id), (11, job_id));
+
+        let binary = fake_prover("local-published", &block, JobStatus::Success);
+        let backend: Arc<dyn ProvingBackend> = Arc::new(LocalBackend::new(&binary).unwrap());
+        let job = fetch_job(&zksync_client, block).await.unwrap();
+        prove_block(
+            zksync_client,
+            backend,
+            job,
+            &ledger,
+            &reputation,
+            Duration::from_secs(60),
+            false,
         )
-    })?;
-    Ok(())
+        .await
+        .unwrap();
+
+        let published = server.published();
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].block_id, 11);
+        assert_eq!(server.heartbeats(job_id), 1);
+        assert_eq!(reputation.stats("local").unwrap().completed, 1);
+    }
+
+    #[cfg(unix)]
+    #[actix_rt::test]
+    async fn local_prover_failure_is_not_published() {
+        let server = MockServer::start(None).unwrap();
+        // Debug files are named by job id and tests share working directory.
+        server.add_block(100, 30, empty_prover_data());
+        server.add_block(12, 6, empty_prover_data());
+        let zksync_client = client(&server, Auth::None, fast_retry(2));
+        let ledger = ledger("local-failed");
+        let reputation = reputation("local-failed");
+
+        let block = ask_for_block(&zksync_client, &[6]).await.unwrap().unwrap();
+        let binary = fake_prover("local-failed", &block, JobStatus::Failed);
+        let backend: Arc<dyn ProvingBackend> = Arc::new(LocalBackend::new(&binary).unwrap());
+        let job = fetch_job(&zksync_client, block).await.unwrap();
+        let result = prove_block(
+            zksync_client,
+            backend,
+            job,
+            &ledger,
+            &reputation,
+            Duration::from_secs(60),
+            false,
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(server.published().is_empty());
+        assert_eq!(reputation.stats("local").unwrap().failures, 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[actix_rt::test]
+    async fn hanging_local_prover_is_killed_on_teardown() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let server = MockServer::start(None).unwrap();
+        let zksync_client = client(&server, Auth::None, fast_retry(0));
+        let ledger = ledger("local-hanging");
+        let reputation = reputation("local-hanging");
+
+        let dir = workdir().join("local-hanging");
+        fs::create_dir_all(&dir).unwrap();
+        let pid_file = dir.join("pid");
+        let script = dir.join("yagna-prover");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho $$ > {}\nexec sleep 60\n",
+                pid_file.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let backend: Arc<dyn ProvingBackend> = Arc::new(LocalBackend::new(&script).unwrap());
+        let result = prove_block(
+            zksync_client,
+            backend.clone(),
+            prepared_job(300, 300),
+            &ledger,
+            &reputation,
+            Duration::from_secs(1),
+            false,
+        )
+        .await;
+        assert!(result
+            .unwrap_err()
+            .downcast_ref::<ProverTimeout>()
+            .is_some());
+        assert_eq!(reputation.stats("local").unwrap().timeouts, 1);
+
+        backend.teardown().await.unwrap();
+        let pid = fs::read_to_string(&pid_file).unwrap();
+        let process = PathBuf::from(format!("/proc/{}", pid.trim()));
+        for _ in 0..50 {
+            if !process.exists() {
+                break;
+            }
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+        }
+        assert!(!process.exists());
+    }
+
+    fn prepared_job(block_id: i64, job_id: i32) -> PreparedJob {
+        PreparedJob {
+            block: BlockInfo {
+                block_id,
+                job_id,
+                block_size: 6,
+            },
+            data: empty_prover_data(),
+            uploaded: false,
+        }
+    }
+
+    fn fake_backend(job: &PreparedJob, status: JobStatus, return_code: i32) -> Arc<FakeBackend> {
+        let block_id = job.block.block_id;
+        let mut backend = FakeBackend::new(return_code).with_result(
+            &format!("/proofs/result-{}.json", block_id),
+            &manifest(&job.block, status),
+        );
+        if status == JobStatus::Success {
+            backend = backend.with_result(
+                &format!("/proofs/proof-{}.json", block_id),
+                &EncodedProofPlonk::default(),
+            );
+        }
+        Arc::new(backend)
+    }
+
+    async fn prove_on_fake(
+        name: &str,
+        job: PreparedJob,
+        backend: Arc<FakeBackend>,
+        batch: bool,
+    ) -> (MockServer, ReputationStore, anyhow::Result<()>) {
+        workdir();
+        let server = MockServer::start(None).unwrap();
+        let zksync_client = client(&server, Auth::None, fast_retry(2));
+        let reputation = reputation(name);
+        let result = prove_block(
+            zksync_client,
+            backend,
+            job,
+            &ledger(name),
+            &reputation,
+            Duration::from_secs(60),
+            batch,
+        )
+        .await;
+        (server, reputation, result)
+    }
+
+    #[actix_rt::test]
+    async fn proof_is_uploaded_proved_and_published() {
+        let job = prepared_job(21, 1);
+        let backend = fake_backend(&job, JobStatus::Success, 0);
+        let (server, reputation, result) =
+            prove_on_fake("fake-published", job, backend.clone(), false).await;
+
+        result.unwrap();
+        assert!(backend.file("/blocks/block-21.json").is_some());
+        assert_eq!(
+            backend.file("/blocks/job-info.json").unwrap()["block_id"],
+            21
+        );
+        assert_eq!(backend.runs(), vec![vec!["ya-prover".to_string()]]);
+        assert_eq!(server.published()[0].block_id, 21);
+        assert_eq!(server.heartbeats(1), 1);
+        assert_eq!(reputation.stats("fake").unwrap().completed, 1);
+    }
+
+    #[actix_rt::test]
+    async fn invalid_proof_is_not_published() {
+        let job = prepared_job(22, 2);
+        let backend = fake_backend(&job, JobStatus::InvalidProof, 3);
+        let (server, reputation, result) = prove_on_fake("fake-invalid", job, backend, false).await;
+
+        assert!(result.is_err());
+        assert!(server.published().is_empty());
+        assert_eq!(server.heartbeats(2), 0);
+        assert_eq!(reputation.stats("fake").unwrap().invalid_proofs, 1);
+    }
+
+    #[actix_rt::test]
+    async fn missing_manifest_fails_job() {
+        let job = prepared_job(23, 3);
+        let backend = Arc::new(FakeBackend::new(0));
+        let (server, reputation, result) =
+            prove_on_fake("fake-no-manifest", job, backend, false).await;
+
+        assert!(result.is_err());
+        assert!(server.published().is_empty());
+        assert_eq!(reputation.stats("fake").unwrap().failures, 1);
+    }
+
+    #[actix_rt::test]
+    async fn batch_job_is_published() {
+        let job = prepared_job(24, 4);
+        let backend = fake_backend(&job, JobStatus::Success, 0);
+        let (server, _, result) = prove_on_fake("fake-batch", job, backend.clone(), true).await;
+
+        result.unwrap();
+        assert!(backend.file("/blocks/block-24.json").is_some());
+        assert_eq!(backend.runs().len(), 1);
+        assert_eq!(server.published()[0].block_id, 24);
+    }
+
+    #[actix_rt::test]
+    async fn batch_job_reads_manifest_after_failed_prover() {
+        let job = prepared_job(25, 5);
+        let backend = fake_backend(&job, JobStatus::InvalidProof, 3);
+        let (server, reputation, result) =
+            prove_on_fake("fake-batch-invalid", job, backend, true).await;
+
+        // Downloads in script were skipped, so manifest had to be downloaded separately.
+        assert!(result.unwrap_err().to_string().contains("verification"));
+        assert!(server.published().is_empty());
+        assert_eq!(reputation.stats("fake").unwrap().invalid_proofs, 1);
+    }
 }
@@ -0,0 +1,285 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use structopt::StructOpt;
+
+use prover_common::artifact::{read_json, write_json_atomic};
+
+/// Reputation database in working directory.
+pub const REPUTATION_FILE: &str = "reputation.json";
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct ReputationArgs {
+    /// Node ids of the only Providers, we make agreements with. Comma separated.
+    #[structopt(long, env, use_delimiter = true)]
+    pub allow_providers: Vec<String>,
+    /// Node ids of Providers, we never make agreements with. Comma separated.
+    #[structopt(long, env, use_delimiter = true)]
+    pub deny_providers: Vec<String>,
+    /// Providers with lower ratio of completed proofs are avoided.
+    #[structopt(long, env, default_value = "0.5")]
+    pub min_success_rate: f64,
+    /// Jobs needed, before success rate is taken into account.
+    #[structopt(long, env, default_value = "3")]
+    pub min_jobs: u64,
+}
+
+/// Result of single job from reputation point of view.
+#[derive(Clone, Copy, Debug)]
+pub enum Outcome {
+    Completed {
+        block_size: usize,
+        duration_sec: f64,
+    },
+    Failed,
+    Timeout,
+    InvalidProof,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SizeStats {
+    pub completed: u64,
+    pub total_duration_sec: f64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ProviderStats {
+    pub completed: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    pub invalid_proofs: u64,
+    pub by_block_size: BTreeMap<usize, SizeStats>,
+    pub last_job: Option<DateTime<Utc>>,
+}
+
+impl ProviderStats {
+    pub fn jobs(&self) -> u64 {
+        self.completed + self.failures + self.timeouts + self.invalid_proofs
+    }
+
+    /// Success rate smoothed, so new Providers start at 0.5.
+    pub fn score(&self) -> f64 {
+        (self.completed as f64 + 1.0) / (self.jobs() as f64 + 2.0)
+    }
+
+    /// Mean proof duration over all block sizes.
+    pub fn mean_duration_sec(&self) -> Option<f64> {
+        let (completed, total) =
+            self.by_block_size
+                .values()
+                .fold((0, 0.0), |(completed, total), size| {
+                    (completed + size.completed, total + size.total_duration_sec)
+                });
+        match completed {
+            0 => None,
+            completed => Some(total / completed as f64),
+        }
+    }
+}
+
+/// Persistent history of Providers keyed by node id.
+pub struct ReputationStore {
+    path: PathBuf,
+    args: ReputationArgs,
+    providers: Mutex<HashMap<String, ProviderStats>>,
+}
+
+impl ReputationStore {
+    pub fn load(path: &Path, args: ReputationArgs) -> anyhow::Result<ReputationStore> {
+        let providers = read_json(path)?.unwrap_or_default();
+        Ok(ReputationStore {
+            path: path.to_path_buf(),
+            args,
+            providers: Mutex::new(providers),
+        })
+    }
+
+    pub fn record(&self, provider: &str, outcome: Outcome) {
+        let mut providers = self.providers.lock().unwrap();
+        let stats = providers.entry(provider.to_string()).or_default();
+        match outcome {
+            Outcome::Completed {
+                block_size,
+                duration_sec,
+            } => {
+                stats.completed += 1;
+                let size = stats.by_block_size.entry(block_size).or_default();
+                size.completed += 1;
+                size.total_duration_sec += duration_sec;
+            }
+            Outcome::Failed => stats.failures += 1,
+            Outcome::Timeout => stats.timeouts += 1,
+            Outcome::InvalidProof => stats.invalid_proofs += 1,
+        }
+        stats.last_job = Some(Utc::now());
+        log::debug!("Provider [{}] reputation: {:?}", provider, stats);
+
+        write_json_atomic(&self.path, &*providers)
+            .map_err(|e| log::warn!("Failed to save reputation. {}", e))
+            .ok();
+    }
+
+    pub fn stats(&self, provider: &str) -> Option<ProviderStats> {
+        self.providers.lock().unwrap().get(provider).cloned()
+    }
+
+    /// Returns reason, why we shouldn't make agreement with Provider.
+    pub fn check_provider(&self, provider: &str) -> Result<(), String> {
+        if self
+            .args
+            .deny_providers
+            .iter()
+            .any(|denied| denied == provider)
+        {
+            return Err("provider is on deny list".to_string());
+        }
+        if !self.args.allow_providers.is_empty()
+            && !self
+                .args
+                .allow_providers
+                .iter()
+                .any(|allowed| allowed == provider)
+        {
+            return Err("provider is not on allow list".to_string());
+        }
+
+        if let Some(stats) = self.stats(provider) {
+            let success_rate = stats.completed as f64 / stats.jobs().max(1) as f64;
+            if stats.jobs() >= self.args.min_jobs && success_rate < self.args.min_success_rate {
+                return Err(format!(
+                    "provider completed only {} of {} jobs",
+                    stats.completed,
+                    stats.jobs()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn score(&self, provider: &str) -> f64 {
+        self.stats(provider)
+            .map(|stats| stats.score())
+            .unwrap_or(0.5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::workdir;
+
+    fn args(allow: &[&str], deny: &[&str]) -> ReputationArgs {
+        ReputationArgs {
+            allow_providers: allow.iter().map(|id| id.to_string()).collect(),
+            deny_providers: deny.iter().map(|id| id.to_string()).collect(),
+            min_success_rate: 0.5,
+            min_jobs: 3,
+        }
+    }
+
+    fn store(name: &str, args: ReputationArgs) -> ReputationStore {
+        ReputationStore::load(&store_path(name), args).unwrap()
+    }
+
+    fn store_path(name: &str) -> PathBuf {
+        workdir().join(format!("{}-reputation.json", name))
+    }
+
+    fn completed() -> Outcome {
+        Outcome::Completed {
+            block_size: 6,
+            duration_sec: 100.0,
+        }
+    }
+
+    #[test]
+    fn deny_list_rejects_provider() {
+        let store = store("deny-list", args(&[], &["0xbad"]));
+        assert!(store.check_provider("0xbad").is_err());
+        assert!(store.check_provider("0xgood").is_ok());
+    }
+
+    #[test]
+    fn allow_list_accepts_only_listed_providers() {
+        let store = store("allow-list", args(&["0xgood"], &[]));
+        assert!(store.check_provider("0xgood").is_ok());
+        assert!(store.check_provider("0xother").is_err());
+
+        // Deny list wins over allow list.
+        let store = store("allow-deny-list", args(&["0xgood"], &["0xgood"]));
+        assert!(store.check_provider("0xgood").is_err());
+    }
+
+    #[test]
+    fn success_rate_is_ignored_below_min_jobs() {
+        let store = store("few-jobs", args(&[], &[]));
+        store.record("0xa", Outcome::Failed);
+        store.record("0xa", Outcome::Timeout);
+        assert!(store.check_provider("0xa").is_ok());
+
+        store.record("0xa", Outcome::InvalidProof);
+        assert!(store.check_provider("0xa").is_err());
+    }
+
+    #[test]
+    fn success_rate_at_threshold_is_accepted() {
+        let store = store("threshold", args(&[], &[]));
+        store.record("0xa", completed());
+        store.record("0xa", completed());
+        store.record("0xa", Outcome::Failed);
+        store.record("0xa", Outcome::Failed);
+        assert!(store.check_provider("0xa").is_ok());
+
+        store.record("0xa", Outcome::Failed);
+        assert!(store.check_provider("0xa").is_err());
+    }
+
+    #[test]
+    fn score_of_unknown_provider_is_neutral() {
+        let store = store("score", args(&[], &[]));
+        assert_eq!(store.score("0xunknown"), 0.5);
+
+        store.record("0xa", completed());
+        assert!(store.score("0xa") > 0.5);
+        store.record("0xb", Outcome::Failed);
+        assert!(store.score("0xb") < 0.5);
+    }
+
+    #[test]
+    fn missing_store_is_empty() {
+        let store = store("missing", args(&[], &[]));
+        assert!(store.stats("0xa").is_none());
+    }
+
+    #[test]
+    fn recorded_stats_are_saved() {
+        let path = store_path("saved");
+        store("saved", args(&[], &[])).record("0xa", completed());
+        store("saved", args(&[], &[])).record("0xa", Outcome::Failed);
+
+        let stats = ReputationStore::load(&path, args(&[], &[]))
+            .unwrap()
+            .stats("0xa")
+            .unwrap();
+        assert_eq!(stats.completed, 1);
+        assert_eq!(stats.failures, 1);
+        assert_eq!(stats.mean_duration_sec(), Some(100.0));
+    }
+
+    #[test]
+    fn corrupt_store_fails_to_load() {
+        let path = store_path("corrupt");
+        std::fs::write(&path, "[1, 2, 3]").unwrap();
+        assert!(ReputationStore::load(&path, args(&[], &[])).is_err());
+    }
+
+    #[test]
+    fn truncated_store_is_empty() {
+        let path = store_path("truncated");
+        std::fs::write(&path, r#"{"0xa": {"completed": 1, "fail"#).unwrap();
+        let store = ReputationStore::load(&path, args(&[], &[])).unwrap();
+        assert!(store.stats("0xa").is_none());
+    }
+}
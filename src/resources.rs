@@ -0,0 +1,208 @@
+use std::sync::{Arc, Mutex};
+
+use ya_client::market::MarketRequestorApi;
+use ya_client_model::market::{NewDemand, Reason};
+
+use crate::backend::ProvingBackend;
+use crate::zksync_client::ZksyncClient;
+
+/// Why we stop working with Provider. Sent to Provider, when terminating agreement.
+#[derive(Clone, Debug)]
+pub enum Termination {
+    /// Work is done or Provider isn't needed anymore.
+    Finished,
+    /// Requestor was stopped by user.
+    Interrupted,
+    Failed(String),
+}
+
+impl Termination {
+    fn reason(&self) -> Reason {
+        let (message, code) = match self {
+            Termination::Finished => ("Work finished".to_string(), "Success"),
+            Termination::Interrupted => ("Requestor interrupted".to_string(), "Cancelled"),
+            Termination::Failed(e) => (e.clone(), "Error"),
+        };
+        Reason {
+            message,
+            extra: serde_json::json!({ "golem.requestor.code": code }),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Resources {
+    backends: Vec<Arc<dyn ProvingBackend>>,
+    /// Agreements created, but not approved by Provider yet.
+    pending_agreements: Vec<String>,
+    agreements: Vec<String>,
+    subscriptions: Vec<String>,
+    prover: Option<(Arc<ZksyncClient>, i32)>,
+}
+
+/// Keeps track of everything we created on yagna network and zksync server,
+/// so it can be released in order on every exit path: activities, agreements,
+/// demand subscriptions and at the end prover registration.
+pub struct ResourceGuard {
+    market: MarketRequestorApi,
+    resources: Mutex<Resources>,
+}
+
+impl ResourceGuard {
+    pub fn new(market: MarketRequestorApi) -> ResourceGuard {
+        ResourceGuard {
+            market,
+            resources: Mutex::new(Resources::default()),
+        }
+    }
+
+    pub fn market(&self) -> &MarketRequestorApi {
+        &self.market
+    }
+
+    pub async fn subscribe(&self, demand: &NewDemand) -> anyhow::Result<String> {
+        let subscription_id = self.market.subscribe(demand).await?;
+        self.resources
+            .lock()
+            .unwrap()
+            .subscriptions
+            .push(subscription_id.clone());
+        Ok(subscription_id)
+    }
+
+    pub async fn unsubscribe(&self, subscription_id: &str) {
+        self.resources
+            .lock()
+            .unwrap()
+            .subscriptions
+            .retain(|id| id != subscription_id);
+
+        log::debug!("Unsubscribing demand [{}]..", subscription_id);
+        self.market
+            .unsubscribe(subscription_id)
+            .await
+            .map_err(|e| {
+                log::warn!(
+                    "Can't unsubscribe demand [{}]. Error: {}",
+                    subscription_id,
+                    e
+                )
+            })
+            .ok();
+    }
+
+    /// Agreement, that isn't approved yet, can only be cancelled.
+    pub fn add_pending_agreement(&self, agreement_id: &str) {
+        self.resources
+            .lock()
+            .unwrap()
+            .pending_agreements
+            .push(agreement_id.to_string());
+    }
+
+    /// Approved agreement must be terminated, when we don't need it anymore.
+    pub fn add_agreement(&self, agreement_id: &str) {
+        let mut resources = self.resources.lock().unwrap();
+        resources.pending_agreements.retain(|id| id != agreement_id);
+        resources.agreements.push(agreement_id.to_string());
+    }
+
+    pub async fn cancel_agreement(&self, agreement_id: &str) {
+        self.resources
+            .lock()
+            .unwrap()
+            .pending_agreements
+            .retain(|id| id != agreement_id);
+
+        log::debug!("Cancelling agreement [{}]..", agreement_id);
+        self.market
+            .cancel_agreement(agreement_id)
+            .await
+            .map_err(|e| log::warn!("Can't cancel agreement [{}]. Error: {}", agreement_id, e))
+            .ok();
+    }
+
+    pub async fn terminate_agreement(&self, agreement_id: &str, termination: &Termination) {
+        self.resources
+            .lock()
+            .unwrap()
+            .agreements
+            .retain(|id| id != agreement_id);
+
+        log::info!(
+            "Terminating agreement [{}]. Reason: {:?}.",
+            agreement_id,
+            termination
+        );
+        self.market
+            .terminate_agreement(agreement_id, &Some(termination.reason()))
+            .await
+            .map_err(|e| log::error!("Can't terminate agreement [{}]. Error: {}", agreement_id, e))
+            .ok();
+    }
+
+    pub fn add_backend(&self, backend: Arc<dyn ProvingBackend>) {
+        self.resources.lock().unwrap().backends.push(backend);
+    }
+
+    /// Destroys backend and terminates agreement, it was created for.
+    pub async fn release_backend(
+        &self,
+        backend: &Arc<dyn ProvingBackend>,
+        termination: &Termination,
+    ) {
+        self.resources
+            .lock()
+            .unwrap()
+            .backends
+            .retain(|other| !Arc::ptr_eq(other, backend));
+
+        log::info!("Destroying {}..", backend.name());
+        backend
+            .teardown()
+            .await
+            .map_err(|e| log::error!("Can't destroy {}. Error: {}", backend.name(), e))
+            .ok();
+
+        if let Some(agreement_id) = backend.agreement_id() {
+            self.terminate_agreement(&agreement_id, termination).await;
+        }
+    }
+
+    pub fn add_prover(&self, zksync_client: Arc<ZksyncClient>, prover_id: i32) {
+        self.resources.lock().unwrap().prover = Some((zksync_client, prover_id));
+    }
+
+    /// Releases all resources, that are still held.
+    pub async fn release_all(&self, termination: &Termination) {
+        let backends = std::mem::take(&mut self.resources.lock().unwrap().backends);
+        for backend in backends {
+            self.release_backend(&backend, termination).await;
+        }
+
+        let agreements = self.resources.lock().unwrap().agreements.clone();
+        for agreement_id in agreements {
+            self.terminate_agreement(&agreement_id, termination).await;
+        }
+
+        let pending = self.resources.lock().unwrap().pending_agreements.clone();
+        for agreement_id in pending {
+            self.cancel_agreement(&agreement_id).await;
+        }
+
+        let subscriptions = self.resources.lock().unwrap().subscriptions.clone();
+        for subscription_id in subscriptions {
+            self.unsubscribe(&subscription_id).await;
+        }
+
+        let prover = self.resources.lock().unwrap().prover.take();
+        if let Some((zksync_client, prover_id)) = prover {
+            log::info!("Stopping prover on zksync server..");
+            zksync_client
+                .prover_stopped(prover_id)
+                .await
+                .map_err(|e| log::error!("Failed to unregister prover on server. Error: {}", e))
+                .ok();
+        }
+    }
+}
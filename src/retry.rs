@@ -0,0 +1,93 @@
+use std::fmt;
+use std::future::Future;
+
+pub use prover_common::retry::RetryPolicy;
+
+/// Runs `operation` until it succeeds, returns `backoff::Error::Permanent`
+/// or the policy gives up. The last error is returned in both failure cases.
+pub async fn retry<T, E, F, Fut>(policy: &RetryPolicy, what: &str, mut operation: F) -> Result<T, E>
+where
+    E: fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, backoff::Error<E>>>,
+{
+    let mut retries = policy.start();
+    loop {
+        let error = match operation().await {
+            Ok(result) => return Ok(result),
+            Err(backoff::Error::Permanent(e)) => return Err(e),
+            Err(backoff::Error::Transient(e)) => e,
+        };
+
+        match retries.after_failure(what, &error) {
+            Some(wait) => tokio::time::delay_for(wait).await,
+            None => return Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    fn fast_policy(retries: usize) -> RetryPolicy {
+        RetryPolicy {
+            initial_interval: Duration::from_millis(1),
+            max_interval: Duration::from_millis(1),
+            max_retries: Some(retries),
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn transient_errors_are_retried() {
+        let attempts = Cell::new(0);
+        let result = retry(&fast_policy(5), "test", || {
+            attempts.set(attempts.get() + 1);
+            let attempt = attempts.get();
+            async move {
+                match attempt {
+                    1..=2 => Err(backoff::Error::Transient("temporary")),
+                    _ => Ok(attempt),
+                }
+            }
+        })
+        .await;
+        assert_eq!(result, Ok(3));
+    }
+
+    #[actix_rt::test]
+    async fn permanent_error_stops_retrying() {
+        let attempts = Cell::new(0);
+        let result: Result<(), _> = retry(&fast_policy(5), "test", || {
+            attempts.set(attempts.get() + 1);
+            async { Err(backoff::Error::Permanent("permanent")) }
+        })
+        .await;
+        assert_eq!(result, Err("permanent"));
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn gives_up_after_max_retries() {
+        let attempts = Cell::new(0);
+        let result: Result<(), _> = retry(&fast_policy(2), "test", || {
+            attempts.set(attempts.get() + 1);
+            async { Err(backoff::Error::Transient("temporary")) }
+        })
+        .await;
+        assert_eq!(result, Err("temporary"));
+        assert_eq!(attempts.get(), 3);
+
+        attempts.set(0);
+        let result: Result<(), _> = retry(&RetryPolicy::no_retry(), "test", || {
+            attempts.set(attempts.get() + 1);
+            async { Err(backoff::Error::Transient("temporary")) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+}
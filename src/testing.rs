@@ -0,0 +1,78 @@
+//! Helpers shared by tests running requestor against `MockServer`.
+use std::path::PathBuf;
+use std::sync::{Arc, Once};
+use std::time::Duration;
+
+use mock_prover_server::MockServer;
+
+use crate::auth::Auth;
+use crate::costs::CostLedger;
+use crate::reputation::{ReputationArgs, ReputationStore};
+use crate::retry::RetryPolicy;
+use crate::zksync_client::{ConnectionArgs, RetryArgs, ZksyncClient};
+
+/// Requestor writes debug files relative to working directory, so all tests
+/// run in the same temporary directory instead of the repository.
+pub fn workdir() -> PathBuf {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let dir = tempfile::Builder::new()
+            .prefix("ya-zksync-node-test-")
+            .tempdir()
+            .expect("Can't create test workdir")
+            .into_path();
+        std::env::set_current_dir(&dir).expect("Can't enter test workdir");
+    });
+    std::env::current_dir().unwrap()
+}
+
+/// Retries quickly, so tests with injected faults don't wait.
+pub fn fast_retry(retries: usize) -> RetryArgs {
+    RetryArgs {
+        retry_policy: RetryPolicy {
+            initial_interval: Duration::from_millis(10),
+            max_interval: Duration::from_millis(10),
+            max_retries: Some(retries),
+            ..RetryPolicy::default()
+        },
+        retry_register: None,
+        retry_block_to_prove: None,
+        retry_working_on: None,
+        retry_prover_data: None,
+        retry_publish: None,
+        retry_stopped: None,
+    }
+}
+
+pub fn client(server: &MockServer, auth: Auth, retry: RetryArgs) -> Arc<ZksyncClient> {
+    let connection = ConnectionArgs {
+        server_ca_bundle: None,
+        server_client_identity: None,
+        server_client_identity_password: String::new(),
+        server_proxy: None,
+        user_agent: None,
+    };
+    ZksyncClient::new(
+        &server.url().parse().unwrap(),
+        "test-worker",
+        Duration::from_secs(10),
+        retry,
+        auth,
+        &connection,
+    )
+    .unwrap()
+}
+
+pub fn ledger(name: &str) -> CostLedger {
+    CostLedger::new(&workdir().join(format!("{}-costs.jsonl", name)))
+}
+
+pub fn reputation(name: &str) -> ReputationStore {
+    let args = ReputationArgs {
+        allow_providers: vec![],
+        deny_providers: vec![],
+        min_success_rate: 0.5,
+        min_jobs: 3,
+    };
+    ReputationStore::load(&workdir().join(format!("{}-reputation.json", name)), args).unwrap()
+}
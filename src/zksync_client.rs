@@ -1,18 +1,273 @@
 // Built-in deps
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::time::{self, Duration};
+use std::time;
 // External deps
-use anyhow::bail;
-use anyhow::format_err;
+use anyhow::{anyhow, bail};
+use futures::TryFutureExt;
 use log::*;
-use reqwest::Url;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Certificate, Identity, Method, Proxy, StatusCode, Url};
+use serde::Serialize;
 use std::sync::Arc;
+use structopt::StructOpt;
 // Workspace deps
-use backoff::backoff::Backoff;
+use crate::auth::{authorize, Auth, AuthArgs};
+use crate::retry::{retry, RetryPolicy};
+use prover_common::network::{split_pem_bundle, NetworkConfig};
+use prover_common::retry::{is_retryable_status, DUPLICATE_PROOF_RESPONSE};
 use zksync_crypto::proof::EncodedProofPlonk;
 use zksync_prover_utils::api::{BlockToProveRes, ProverReq, PublishReq, WorkingOnReq};
 use zksync_prover_utils::prover_data::ProverData;
 
+#[derive(Debug, thiserror::Error)]
+pub enum ZksyncClientError {
+    #[error("{endpoint} request failed: {source}")]
+    Network {
+        endpoint: Endpoint,
+        source: reqwest::Error,
+    },
+    #[error("{endpoint} request failed with status: {status} and message: {body}")]
+    HttpStatus {
+        endpoint: Endpoint,
+        status: StatusCode,
+        body: String,
+    },
+    #[error("failed to serialize {endpoint} request: {source}")]
+    InvalidRequest {
+        endpoint: Endpoint,
+        source: serde_json::Error,
+    },
+    #[error("failed to parse {endpoint} response: {message}")]
+    MalformedResponse { endpoint: Endpoint, message: String },
+    #[error("ProverData for block {0} is not ready yet")]
+    BlockNotReady(i64),
+    #[error("proof for block {0} already exists")]
+    ProofAlreadyExists(i64),
+    #[error("prover registration rejected with status: {status} and message: {body}")]
+    RegistrationRejected { status: StatusCode, body: String },
+}
+
+impl ZksyncClientError {
+    /// Connection failures, server errors (5xx) and data not being ready yet
+    /// are temporary. Client errors (4xx) and malformed responses
+    /// will be repeated on every attempt.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ZksyncClientError::Network { source, .. } => !source.is_builder(),
+            ZksyncClientError::HttpStatus { status, .. } => is_retryable_status(status.as_u16()),
+            ZksyncClientError::BlockNotReady(_) => true,
+            ZksyncClientError::InvalidRequest { .. }
+            | ZksyncClientError::MalformedResponse { .. }
+            | ZksyncClientError::ProofAlreadyExists(_)
+            | ZksyncClientError::RegistrationRejected { .. } => false,
+        }
+    }
+}
+
+/// Endpoints of zksync prover server used by `ZksyncClient`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    Register,
+    BlockToProve,
+    WorkingOn,
+    ProverData,
+    Publish,
+    Stopped,
+}
+
+impl Endpoint {
+    pub fn path(&self) -> &'static str {
+        match self {
+            Endpoint::Register => "/register",
+            Endpoint::BlockToProve => "/block_to_prove",
+            Endpoint::WorkingOn => "/working_on",
+            Endpoint::ProverData => "/prover_data",
+            Endpoint::Publish => "/publish",
+            Endpoint::Stopped => "/stopped",
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", &self.path()[1..])
+    }
+}
+
+/// Retry policies for zksync server endpoints. Endpoints without
+/// explicitly set policy use `retry_policy`.
+#[derive(StructOpt, Debug, Clone)]
+pub struct RetryArgs {
+    /// Defaults to `RetryPolicy::default()`.
+    #[structopt(long, env, default_value)]
+    pub retry_policy: RetryPolicy,
+    #[structopt(long, env)]
+    pub retry_register: Option<RetryPolicy>,
+    #[structopt(long, env)]
+    pub retry_block_to_prove: Option<RetryPolicy>,
+    #[structopt(long, env)]
+    pub retry_working_on: Option<RetryPolicy>,
+    #[structopt(long, env)]
+    pub retry_prover_data: Option<RetryPolicy>,
+    #[structopt(long, env)]
+    pub retry_publish: Option<RetryPolicy>,
+    #[structopt(long, env)]
+    pub retry_stopped: Option<RetryPolicy>,
+}
+
+impl RetryArgs {
+    pub fn policy(&self, endpoint: Endpoint) -> &RetryPolicy {
+        let policy = match endpoint {
+            Endpoint::Register => &self.retry_register,
+            Endpoint::BlockToProve => &self.retry_block_to_prove,
+            Endpoint::WorkingOn => &self.retry_working_on,
+            Endpoint::ProverData => &self.retry_prover_data,
+            Endpoint::Publish => &self.retry_publish,
+            Endpoint::Stopped => &self.retry_stopped,
+        };
+        policy.as_ref().unwrap_or(&self.retry_policy)
+    }
+}
+
+/// Options of http connection to zksync server.
+#[derive(StructOpt, Debug, Clone)]
+pub struct ConnectionArgs {
+    /// PEM file with additional CA certificates to trust.
+    #[structopt(long, env, parse(from_os_str))]
+    pub server_ca_bundle: Option<PathBuf>,
+    /// PKCS#12 file with client certificate and key for mutual TLS.
+    #[structopt(long, env, parse(from_os_str))]
+    pub server_client_identity: Option<PathBuf>,
+    #[structopt(long, env, default_value = "")]
+    pub server_client_identity_password: String,
+    /// Proxy for all requests to zksync server.
+    #[structopt(long, env)]
+    pub server_proxy: Option<Url>,
+    /// Defaults to `ya-zksync-node/<version> (<worker name>)`.
+    #[structopt(long, env)]
+    pub user_agent: Option<String>,
+}
+
+impl ConnectionArgs {
+    fn client_builder(&self, worker: &str) -> anyhow::Result<reqwest::ClientBuilder> {
+        let user_agent = self.user_agent.clone().unwrap_or_else(|| {
+            format!(
+                "{}/{} ({})",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION"),
+                worker
+            )
+        });
+        let mut builder = reqwest::ClientBuilder::new().user_agent(user_agent);
+
+        if let Some(path) = &self.server_ca_bundle {
+            for cert in load_ca_bundle(path)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        if let Some(path) = &self.server_client_identity {
+            let der = fs::read(path).map_err(|e| {
+                anyhow!(
+                    "Can't read client identity [{}]. Error: {}",
+                    path.display(),
+                    e
+                )
+            })?;
+            let identity = Identity::from_pkcs12_der(&der, &self.server_client_identity_password)
+                .map_err(|e| {
+                anyhow!("Invalid client identity [{}]. Error: {}", path.display(), e)
+            })?;
+            builder = builder.identity(identity);
+        }
+
+        if let Some(proxy) = &self.server_proxy {
+            builder = builder.proxy(Proxy::all(proxy.as_str())?);
+        }
+        Ok(builder)
+    }
+}
+
+/// Prover in network mode must connect to server the same way, as Requestor does.
+pub fn network_config(
+    retry: &RetryArgs,
+    auth: &AuthArgs,
+    connection: &ConnectionArgs,
+) -> anyhow::Result<NetworkConfig> {
+    let endpoints = [
+        Endpoint::Register,
+        Endpoint::BlockToProve,
+        Endpoint::WorkingOn,
+        Endpoint::ProverData,
+        Endpoint::Publish,
+        Endpoint::Stopped,
+    ];
+
+    let ca_bundle = match &connection.server_ca_bundle {
+        Some(path) => Some(
+            fs::read_to_string(path)
+                .map_err(|e| anyhow!("Can't read CA bundle [{}]. Error: {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+    let client_identity = match &connection.server_client_identity {
+        Some(path) => Some(hex::encode(fs::read(path).map_err(|e| {
+            anyhow!(
+                "Can't read client identity [{}]. Error: {}",
+                path.display(),
+                e
+            )
+        })?)),
+        None => None,
+    };
+
+    Ok(NetworkConfig {
+        retry_policy: Some(retry.retry_policy.to_string()),
+        endpoint_retry_policies: endpoints
+            .iter()
+            .map(|endpoint| (endpoint.to_string(), retry.policy(*endpoint).to_string()))
+            .collect(),
+        auth_token: auth.server_auth_token.clone(),
+        hmac_secret: auth.server_hmac_secret.clone(),
+        ca_bundle,
+        client_identity,
+        client_identity_password: connection.server_client_identity_password.clone(),
+        proxy: connection
+            .server_proxy
+            .as_ref()
+            .map(|proxy| proxy.to_string()),
+        user_agent: connection.user_agent.clone(),
+    })
+}
+
+/// Bundle can contain multiple certificates, but `Certificate::from_pem`
+/// parses only the first one, so we must split them.
+fn load_ca_bundle(path: &Path) -> anyhow::Result<Vec<Certificate>> {
+    let bundle = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Can't read CA bundle [{}]. Error: {}", path.display(), e))?;
+    let certs = split_pem_bundle(&bundle)
+        .into_iter()
+        .map(|pem| {
+            Certificate::from_pem(pem.as_bytes()).map_err(|e| {
+                anyhow!(
+                    "Invalid certificate in CA bundle [{}]. Error: {}",
+                    path.display(),
+                    e
+                )
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in CA bundle [{}]", path.display());
+    }
+    Ok(certs)
+}
+
 #[derive(Debug, Clone)]
 pub struct ZksyncClient {
     register_url: Url,
@@ -22,239 +277,434 @@ pub struct ZksyncClient {
     publish_url: Url,
     stopped_url: Url,
     worker: String,
+    retry: RetryArgs,
+    auth: Auth,
     // client keeps connection pool inside, so it is recommended to reuse it (see docstring for reqwest::Client)
     http_client: reqwest::Client,
 }
 
 impl ZksyncClient {
-    pub fn new(base_url: &Url, worker: &str, req_server_timeout: time::Duration) -> Arc<Self> {
-        if worker == "" {
-            panic!("worker name cannot be empty")
+    pub fn new(
+        base_url: &Url,
+        worker: &str,
+        req_server_timeout: time::Duration,
+        retry: RetryArgs,
+        auth: Auth,
+        connection: &ConnectionArgs,
+    ) -> anyhow::Result<Arc<Self>> {
+        if worker.is_empty() {
+            bail!("Worker name cannot be empty");
         }
-        let http_client = reqwest::ClientBuilder::new()
+        let http_client = connection
+            .client_builder(worker)?
             .timeout(req_server_timeout)
             .build()
-            .expect("Failed to create request client");
-        Arc::new(Self {
-            register_url: base_url.join("/register").unwrap(),
-            block_to_prove_url: base_url.join("/block_to_prove").unwrap(),
-            working_on_url: base_url.join("/working_on").unwrap(),
-            prover_data_url: base_url.join("/prover_data").unwrap(),
-            publish_url: base_url.join("/publish").unwrap(),
-            stopped_url: base_url.join("/stopped").unwrap(),
+            .map_err(|e| anyhow!("Failed to create request client. Error: {}", e))?;
+        Ok(Arc::new(Self {
+            register_url: base_url.join(Endpoint::Register.path()).unwrap(),
+            block_to_prove_url: base_url.join(Endpoint::BlockToProve.path()).unwrap(),
+            working_on_url: base_url.join(Endpoint::WorkingOn.path()).unwrap(),
+            prover_data_url: base_url.join(Endpoint::ProverData.path()).unwrap(),
+            publish_url: base_url.join(Endpoint::Publish.path()).unwrap(),
+            stopped_url: base_url.join(Endpoint::Stopped.path()).unwrap(),
             worker: worker.to_string(),
+            retry,
+            auth,
             http_client,
-        })
+        }))
     }
 
-    fn get_backoff() -> backoff::ExponentialBackoff {
-        let mut backoff = backoff::ExponentialBackoff::default();
-        backoff.current_interval = Duration::from_secs(1);
-        backoff.initial_interval = Duration::from_secs(1);
-        backoff.multiplier = 1.5f64;
-        backoff.max_interval = Duration::from_secs(10);
-        backoff.max_elapsed_time = Some(Duration::from_secs(2 * 60));
-        backoff
+    async fn send<T: Serialize + ?Sized>(
+        &self,
+        endpoint: Endpoint,
+        url: &Url,
+        method: Method,
+        body: &T,
+    ) -> Result<reqwest::Response, ZksyncClientError> {
+        let body = serde_json::to_vec(body)
+            .map_err(|source| ZksyncClientError::InvalidRequest { endpoint, source })?;
+        let request = self
+            .http_client
+            .request(method, url.as_str())
+            .header(CONTENT_TYPE, "application/json");
+        authorize(&self.auth, request, endpoint.path(), &body)
+            .body(body)
+            .send()
+            .await
+            .map_err(network_error(endpoint))
+    }
+
+    async fn with_retry<T, F, Fut>(
+        &self,
+        endpoint: Endpoint,
+        operation: F,
+    ) -> Result<T, ZksyncClientError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, ZksyncClientError>>,
+    {
+        let what = format!("{} request", endpoint);
+        retry(self.retry.policy(endpoint), &what, || {
+            operation().map_err(|e| match e.is_retryable() {
+                true => backoff::Error::Transient(e),
+                false => backoff::Error::Permanent(e),
+            })
+        })
+        .await
     }
 
     pub async fn block_to_prove(
         &self,
         block_size: usize,
-    ) -> Result<Option<(i64, i32)>, anyhow::Error> {
-        let mut backoff = Self::get_backoff();
-        loop {
-            match async move {
-                trace!("sending block_to_prove");
-                let res = self
-                    .http_client
-                    .get(self.block_to_prove_url.as_str())
-                    .json(&ProverReq {
+    ) -> Result<Option<(i64, i32)>, ZksyncClientError> {
+        self.with_retry(Endpoint::BlockToProve, || async move {
+            trace!("sending block_to_prove");
+            let res = self
+                .send(
+                    Endpoint::BlockToProve,
+                    &self.block_to_prove_url,
+                    Method::GET,
+                    &ProverReq {
                         name: self.worker.clone(),
                         block_size,
-                    })
-                    .send()
-                    .await
-                    .map_err(|e| format_err!("block to prove request failed: {}", e))?;
-                let text = res
-                    .text()
-                    .await
-                    .map_err(|e| format_err!("failed to read block to prove response: {}", e))?;
-                let res: BlockToProveRes = serde_json::from_str(&text)
-                    .map_err(|e| format_err!("failed to parse block to prove response: {}", e))?;
-                if res.block != 0 {
-                    return Result::<Option<(i64, i32)>, anyhow::Error>::Ok(Some((
-                        res.block,
-                        res.prover_run_id,
-                    )));
-                }
-                Ok(None)
+                    },
+                )
+                .await?;
+            let text = read_response(Endpoint::BlockToProve, res).await?;
+            let res: BlockToProveRes =
+                serde_json::from_str(&text).map_err(malformed(Endpoint::BlockToProve))?;
+            if res.block != 0 {
+                return Ok(Some((res.block, res.prover_run_id)));
             }
-            .await
-            {
-                Ok(result) => return Ok(result),
-                Err(e) => {
-                    if let Some(wait) = backoff.next_backoff() {
-                        tokio::time::delay_for(wait.clone()).await;
-                        warn!(
-                            "Failed to reach server err: <{}>, retrying after: {:.1}s",
-                            e,
-                            wait.as_millis() as f32 / 1000.0f32,
-                        );
-                    } else {
-                        bail!("Prover can't reach server. Max time elapsed.")
-                    }
+            Ok(None)
+        })
+        .await
+    }
+
+    pub async fn working_on(&self, job_id: i32) -> Result<(), ZksyncClientError> {
+        self.with_retry(Endpoint::WorkingOn, || async move {
+            trace!("sending working_on {}", job_id);
+            let res = self
+                .send(
+                    Endpoint::WorkingOn,
+                    &self.working_on_url,
+                    Method::POST,
+                    &WorkingOnReq {
+                        prover_run_id: job_id,
+                    },
+                )
+                .await?;
+            read_response(Endpoint::WorkingOn, res).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn prover_data(&self, block: i64) -> Result<ProverData, ZksyncClientError> {
+        self.with_retry(Endpoint::ProverData, || async move {
+            trace!("sending prover_data");
+            let res = self
+                .send(
+                    Endpoint::ProverData,
+                    &self.prover_data_url,
+                    Method::GET,
+                    &block,
+                )
+                .await?;
+            let text = read_response(Endpoint::ProverData, res).await?;
+            let res: Option<ProverData> =
+                serde_json::from_str(&text).map_err(malformed(Endpoint::ProverData))?;
+            res.ok_or(ZksyncClientError::BlockNotReady(block))
+        })
+        .await
+    }
+
+    pub async fn publish(
+        &self,
+        block: i64,
+        proof: EncodedProofPlonk,
+    ) -> Result<(), ZksyncClientError> {
+        let proof = &proof;
+        self.with_retry(Endpoint::Publish, || async move {
+            trace!("Trying publish proof {}", block);
+            let res = self
+                .send(
+                    Endpoint::Publish,
+                    &self.publish_url,
+                    Method::POST,
+                    &PublishReq {
+                        block: block as u32,
+                        proof: proof.clone(),
+                    },
+                )
+                .await?;
+            match read_response(Endpoint::Publish, res).await {
+                Err(ZksyncClientError::HttpStatus { body, .. })
+                    if body == DUPLICATE_PROOF_RESPONSE =>
+                {
+                    Err(ZksyncClientError::ProofAlreadyExists(block))
                 }
+                result => result.map(|_| ()),
             }
-        }
+        })
+        .await
     }
 
-    pub async fn working_on(&self, job_id: i32) -> Result<(), anyhow::Error> {
-        trace!("sending working_on {}", job_id);
-        let res = self
-            .http_client
-            .post(self.working_on_url.as_str())
-            .json(&WorkingOnReq {
-                prover_run_id: job_id,
-            })
-            .send()
-            .await
-            .map_err(|e| format_err!("failed to send working on request: {}", e))?;
-        if res.status() != reqwest::StatusCode::OK {
-            bail!("working on request failed with status: {}", res.status())
-        } else {
+    pub async fn prover_stopped(&self, prover_run_id: i32) -> Result<(), ZksyncClientError> {
+        self.with_retry(Endpoint::Stopped, || async move {
+            let res = self
+                .send(
+                    Endpoint::Stopped,
+                    &self.stopped_url,
+                    Method::POST,
+                    &prover_run_id,
+                )
+                .await?;
+            read_response(Endpoint::Stopped, res).await?;
             Ok(())
-        }
+        })
+        .await
     }
 
-    pub async fn prover_data(&self, block: i64) -> Result<ProverData, anyhow::Error> {
-        let mut backoff = Self::get_backoff();
-        loop {
-            match async move {
-                trace!("sending prover_data");
-                let res = self
-                    .http_client
-                    .get(self.prover_data_url.as_str())
-                    .json(&block)
-                    .send()
-                    .await
-                    .map_err(|e| format_err!("failed to request prover data: {}", e))?;
-                let text = res
-                    .text()
-                    .await
-                    .map_err(|e| format_err!("failed to read prover data response: {}", e))?;
-                let res: Option<ProverData> = serde_json::from_str(&text)
-                    .map_err(|e| format_err!("failed to parse prover data response: {}", e))?;
-                Result::<ProverData, anyhow::Error>::Ok(res.ok_or_else(|| {
-                    format_err!("ProverData for block {} is not ready yet", block)
-                })?)
-            }
-            .await
-            {
-                Ok(result) => return Ok(result),
-                Err(e) => {
-                    if let Some(wait) = backoff.next_backoff() {
-                        tokio::time::delay_for(wait.clone()).await;
-                        warn!(
-                            "Failed to reach server err: <{}>, retrying after: {:.1}s",
-                            e,
-                            wait.as_millis() as f32 / 1000.0f32,
-                        );
-                    } else {
-                        bail!("Prover can't reach server. Max time elapsed.")
-                    }
+    pub async fn register_prover(&self, block_size: usize) -> Result<i32, ZksyncClientError> {
+        debug!("Registering prover... Block size: {}", block_size);
+        self.with_retry(Endpoint::Register, || async move {
+            let res = self
+                .send(
+                    Endpoint::Register,
+                    &self.register_url,
+                    Method::POST,
+                    &ProverReq {
+                        name: self.worker.clone(),
+                        block_size,
+                    },
+                )
+                .await?;
+
+            let text = match read_response(Endpoint::Register, res).await {
+                Err(ZksyncClientError::HttpStatus { status, body, .. })
+                    if status.is_client_error() =>
+                {
+                    return Err(ZksyncClientError::RegistrationRejected { status, body })
                 }
-            }
+                result => result?,
+            };
+            i32::from_str(&text).map_err(malformed(Endpoint::Register))
+        })
+        .await
+    }
+}
+
+fn network_error(endpoint: Endpoint) -> impl FnOnce(reqwest::Error) -> ZksyncClientError {
+    move |source| ZksyncClientError::Network { endpoint, source }
+}
+
+fn malformed<E: fmt::Display>(endpoint: Endpoint) -> impl FnOnce(E) -> ZksyncClientError {
+    move |e| ZksyncClientError::MalformedResponse {
+        endpoint,
+        message: e.to_string(),
+    }
+}
+
+async fn read_response(
+    endpoint: Endpoint,
+    res: reqwest::Response,
+) -> Result<String, ZksyncClientError> {
+    let status = res.status();
+    let body = res.text().await.map_err(network_error(endpoint))?;
+    if !status.is_success() {
+        return Err(ZksyncClientError::HttpStatus {
+            endpoint,
+            status,
+            body,
+        });
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{client, fast_retry};
+    use mock_prover_server::{
+        empty_prover_data, Endpoint as MockEndpoint, Fault, MockAuth, MockServer,
+    };
+
+    fn http_status(code: u16) -> ZksyncClientError {
+        ZksyncClientError::HttpStatus {
+            endpoint: Endpoint::Publish,
+            status: StatusCode::from_u16(code).unwrap(),
+            body: String::new(),
         }
     }
 
-    pub async fn publish(&self, block: i64, proof: EncodedProofPlonk) -> Result<(), anyhow::Error> {
-        let mut backoff = Self::get_backoff();
-        loop {
-            let proof = proof.clone();
-            match async move {
-                trace!("Trying publish proof {}", block);
-                let proof = proof.clone();
-                let res = self
-                    .http_client
-                    .post(self.publish_url.as_str())
-                    .json(&PublishReq {
-                        block: block as u32,
-                        proof,
-                    })
-                    .send()
-                    .await
-                    .map_err(|e| format_err!("failed to send publish request: {}", e))?;
-                let status = res.status();
-                if status != reqwest::StatusCode::OK {
-                    match res.text().await {
-                        Ok(message) => {
-                            if message == "duplicate key" {
-                                warn!("proof for block {} already exists", block);
-                            } else {
-                                bail!(
-                                    "publish request failed with status: {} and message: {}",
-                                    status,
-                                    message
-                                );
-                            }
-                        }
-                        Err(_) => {
-                            bail!("publish request failed with status: {}", status);
-                        }
-                    };
-                }
+    #[test]
+    fn server_errors_are_retryable() {
+        assert!(http_status(500).is_retryable());
+        assert!(http_status(503).is_retryable());
+        assert!(ZksyncClientError::BlockNotReady(1).is_retryable());
+    }
 
-                Result::<(), anyhow::Error>::Ok(())
-            }
-            .await
-            {
-                Ok(result) => return Ok(result),
-                Err(e) => {
-                    if let Some(wait) = backoff.next_backoff() {
-                        tokio::time::delay_for(wait.clone()).await;
-                        warn!(
-                            "Failed to reach server err: <{}>, retrying after: {:.1}s",
-                            e,
-                            wait.as_millis() as f32 / 1000.0f32,
-                        );
-                    } else {
-                        bail!("Prover can't reach server. Max time elapsed.")
-                    }
-                }
+    #[test]
+    fn client_errors_are_permanent() {
+        assert!(!http_status(400).is_retryable());
+        assert!(!http_status(401).is_retryable());
+        assert!(!ZksyncClientError::ProofAlreadyExists(1).is_retryable());
+        assert!(!ZksyncClientError::MalformedResponse {
+            endpoint: Endpoint::BlockToProve,
+            message: String::new(),
+        }
+        .is_retryable());
+        assert!(!ZksyncClientError::RegistrationRejected {
+            status: StatusCode::FORBIDDEN,
+            body: String::new(),
+        }
+        .is_retryable());
+    }
+
+    #[test]
+    fn empty_worker_name_is_rejected() {
+        let connection = ConnectionArgs {
+            server_ca_bundle: None,
+            server_client_identity: None,
+            server_client_identity_password: String::new(),
+            server_proxy: None,
+            user_agent: None,
+        };
+        let result = ZksyncClient::new(
+            &"http://127.0.0.1:8088".parse().unwrap(),
+            "",
+            time::Duration::from_secs(10),
+            fast_retry(0),
+            Auth::None,
+            &connection,
+        );
+        assert!(result.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn server_errors_are_retried() {
+        let server = MockServer::start(None).unwrap();
+        let job_id = server.add_block(1, 6, empty_prover_data());
+        server.inject(
+            MockEndpoint::BlockToProve,
+            Fault::Status(503, "unavailable".to_string()),
+            2,
+        );
+
+        let client = client(&server, Auth::None, fast_retry(3));
+        assert_eq!(client.block_to_prove(6).await.unwrap(), Some((1, job_id)));
+        assert_eq!(server.requests(MockEndpoint::BlockToProve), 3);
+    }
+
+    #[actix_rt::test]
+    async fn server_errors_exhaust_retries() {
+        let server = MockServer::start(None).unwrap();
+        server.inject(
+            MockEndpoint::WorkingOn,
+            Fault::Status(500, "internal".to_string()),
+            10,
+        );
+
+        let client = client(&server, Auth::None, fast_retry(2));
+        match client.working_on(1).await {
+            Err(ZksyncClientError::HttpStatus { status, .. }) => {
+                assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR)
             }
+            result => panic!("Unexpected result: {:?}", result),
         }
+        assert_eq!(server.requests(MockEndpoint::WorkingOn), 3);
     }
 
-    pub async fn prover_stopped(&self, prover_run_id: i32) -> Result<(), anyhow::Error> {
-        self.http_client
-            .post(self.stopped_url.as_str())
-            .json(&prover_run_id)
-            .send()
-            .await
-            .map_err(|e| format_err!("prover stopped request failed: {}", e))?;
-        Ok(())
+    #[actix_rt::test]
+    async fn duplicate_key_means_proof_already_exists() {
+        let server = MockServer::start(None).unwrap();
+        server.inject(MockEndpoint::Publish, Fault::DuplicateKey, 1);
+
+        let client = client(&server, Auth::None, fast_retry(3));
+        match client.publish(1, EncodedProofPlonk::default()).await {
+            Err(ZksyncClientError::ProofAlreadyExists(1)) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        // Nothing to gain from retrying.
+        assert_eq!(server.requests(MockEndpoint::Publish), 1);
+        assert!(server.published().is_empty());
     }
 
-    pub async fn register_prover(&self, block_size: usize) -> Result<i32, anyhow::Error> {
-        debug!("Registering prover... Block size: {}", block_size);
-        let res = self
-            .http_client
-            .post(self.register_url.as_str())
-            .json(&ProverReq {
-                name: self.worker.clone(),
-                block_size,
-            })
-            .send()
-            .await;
+    #[actix_rt::test]
+    async fn not_ready_data_is_block_not_ready() {
+        let server = MockServer::start(None).unwrap();
+        server.add_block(1, 6, empty_prover_data());
+        server.inject(MockEndpoint::ProverData, Fault::NotReady, 10);
 
-        let res = res.map_err(|e| format_err!("register request failed: {}", e))?;
-        let code = res.status();
-        let text = res
-            .text()
-            .await
-            .map_err(|e| format_err!("failed to read register response: {}", e))?;
+        let client = client(&server, Auth::None, fast_retry(2));
+        match client.prover_data(1).await {
+            Err(ZksyncClientError::BlockNotReady(1)) => (),
+            Err(e) => panic!("Unexpected error: {}", e),
+            Ok(_) => panic!("Data shouldn't be ready"),
+        }
+        assert_eq!(server.requests(MockEndpoint::ProverData), 3);
+    }
+
+    #[actix_rt::test]
+    async fn not_ready_data_is_retried() {
+        let server = MockServer::start(None).unwrap();
+        server.add_block(1, 6, empty_prover_data());
+        server.inject(MockEndpoint::ProverData, Fault::NotReady, 1);
+
+        let client = client(&server, Auth::None, fast_retry(2));
+        assert!(client.prover_data(1).await.is_ok());
+        assert_eq!(server.requests(MockEndpoint::ProverData), 2);
+    }
+
+    #[actix_rt::test]
+    async fn stopped_checks_status() {
+        let server = MockServer::start(None).unwrap();
+        server.inject(
+            MockEndpoint::Stopped,
+            Fault::Status(400, "unknown prover".to_string()),
+            1,
+        );
+
+        let client = client(&server, Auth::None, fast_retry(2));
+        assert!(client.prover_stopped(1).await.is_err());
+        assert!(!server.is_stopped(1));
+    }
+
+    #[actix_rt::test]
+    async fn unauthorized_requests_are_rejected() {
+        let server = MockServer::start(Some(MockAuth::Bearer("secret".to_string()))).unwrap();
+
+        let rejected = client(&server, Auth::Bearer("wrong".to_string()), fast_retry(2));
+        match rejected.register_prover(0).await {
+            Err(ZksyncClientError::RegistrationRejected { status, .. }) => {
+                assert_eq!(status, StatusCode::UNAUTHORIZED)
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        match rejected.block_to_prove(6).await {
+            Err(e @ ZksyncClientError::HttpStatus { .. }) => assert!(!e.is_retryable()),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert!(server.registered_provers().is_empty());
+
+        let accepted = client(&server, Auth::Bearer("secret".to_string()), fast_retry(2));
+        let prover_id = accepted.register_prover(0).await.unwrap();
+        assert_eq!(
+            server.registered_provers(),
+            vec![(prover_id, "test-worker".to_string())]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn hmac_signed_requests_are_accepted() {
+        let secret = b"hmac-secret".to_vec();
+        let server = MockServer::start(Some(MockAuth::Hmac(secret.clone()))).unwrap();
+
+        let rejected = client(&server, Auth::Hmac(b"other".to_vec()), fast_retry(2));
+        assert!(rejected.register_prover(0).await.is_err());
 
-        Ok(i32::from_str(&text)
-            .map_err(|e| format_err!("{}: failed to parse register prover id: {}", code, e))?)
+        let accepted = client(&server, Auth::Hmac(secret), fast_retry(2));
+        assert!(accepted.register_prover(0).await.is_ok());
     }
 }
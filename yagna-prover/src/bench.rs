@@ -0,0 +1,303 @@
+use anyhow::anyhow;
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use structopt::StructOpt;
+
+use zksync_crypto::proof::EncodedProofPlonk;
+use zksync_prover::ApiClient;
+use zksync_prover_utils::prover_data::ProverData;
+
+use crate::client::BlockInfo;
+use crate::manifest::JobTracker;
+use crate::verify::verify_proof;
+use prover_common::artifact::{read_json, write_json_atomic};
+use prover_common::bench::{BenchReport, SizeSummary, BENCH_REPORT_FILE};
+use prover_common::manifest::{JobStatus, ResultManifest};
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct BenchArgs {
+    /// Directory with sample blocks in the same layout, as requestor `blocks`
+    /// directory: `job-info-<job>.json` and `block-<block>.json` files.
+    #[structopt(long, env, default_value = "/bench/", parse(from_os_str))]
+    pub bench_dir: PathBuf,
+    /// How many times each sample is proved.
+    #[structopt(long, env, default_value = "1")]
+    pub bench_repeat: usize,
+}
+
+struct BenchState {
+    pending: VecDeque<BlockInfo>,
+    current: Option<JobTracker>,
+    /// Sizes prover asked for since the last assigned sample.
+    requested: HashSet<usize>,
+    runs: Vec<ResultManifest>,
+    skipped: Vec<i64>,
+}
+
+/// Feeds prover with sample blocks instead of real jobs and measures
+/// each phase. Exits process with report after the last sample.
+#[derive(Clone)]
+pub struct BenchApiClient {
+    state: Arc<Mutex<BenchState>>,
+    bench_dir: PathBuf,
+    proofs_dir: PathBuf,
+}
+
+impl BenchApiClient {
+    pub fn new(
+        args: &BenchArgs,
+        proofs_dir: &Path,
+        supported_sizes: &[usize],
+    ) -> anyhow::Result<Self> {
+        let samples = load_samples(&args.bench_dir)?;
+        if samples.is_empty() {
+            anyhow::bail!("No sample blocks found in [{}].", args.bench_dir.display());
+        }
+
+        let (samples, skipped): (Vec<_>, Vec<_>) = samples
+            .into_iter()
+            .partition(|sample| supported_sizes.contains(&sample.block_size));
+
+        let pending = (0..args.bench_repeat.max(1))
+            .flat_map(|_| samples.iter().cloned())
+            .collect();
+
+        Ok(BenchApiClient {
+            state: Arc::new(Mutex::new(BenchState {
+                pending,
+                current: None,
+                requested: HashSet::new(),
+                runs: vec![],
+                skipped: skipped.iter().map(|sample| sample.block_id).collect(),
+            })),
+            bench_dir: args.bench_dir.clone(),
+            proofs_dir: proofs_dir.to_path_buf(),
+        })
+    }
+
+    fn finish_run(&self, status: JobStatus, error: Option<(&str, String)>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(job) = state.current.take() {
+            let run = job.manifest(status, error);
+            log::info!(
+                "Benchmark block {} of size {}: {:?}. Timings: {:?}",
+                run.block_id,
+                run.block_size,
+                run.status,
+                run.timings
+            );
+            state.runs.push(run);
+        }
+    }
+
+    fn report(state: &BenchState) -> BenchReport {
+        let mut sizes = state
+            .runs
+            .iter()
+            .map(|run| run.block_size)
+            .collect::<Vec<_>>();
+        sizes.sort();
+        sizes.dedup();
+
+        BenchReport {
+            prover_version: env!("CARGO_PKG_VERSION").to_string(),
+            sizes: sizes
+                .into_iter()
+                .map(|size| summarize(size, &state.runs))
+                .collect(),
+            skipped_blocks: state.skipped.clone(),
+            runs: state.runs.clone(),
+        }
+    }
+
+    /// Prints report on stdout and leaves it in proofs directory for Requestor.
+    /// Exits with error, if Requestor won't be able to find the report.
+    fn finish_bench(&self, state: &BenchState) -> ! {
+        let report = BenchApiClient::report(state);
+        if let Ok(json) = serde_json::to_string(&report) {
+            println!("{}", json);
+        }
+        if let Err(e) = write_json_atomic(&self.proofs_dir.join(BENCH_REPORT_FILE), &report) {
+            log::error!("Failed to write benchmark report. {}", e);
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    }
+}
+
+impl ApiClient for BenchApiClient {
+    fn block_to_prove(&self, block_size: usize) -> Result<Option<(i64, i32)>, anyhow::Error> {
+        // Prover asks for next block only after it is done with current one,
+        // so current run ended without publishing proof.
+        if self.state.lock().unwrap().current.is_some() {
+            self.finish_run(
+                JobStatus::Failed,
+                Some(("prover-error", "Prover didn't publish proof.".to_string())),
+            );
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if state.pending.is_empty() {
+            self.finish_bench(&state);
+        }
+
+        let position = state
+            .pending
+            .iter()
+            .position(|sample| sample.block_size == block_size);
+        let sample = match position.and_then(|position| state.pending.remove(position)) {
+            Some(sample) => sample,
+            None => {
+                // Prover asks for all its sizes in turn. After full round without
+                // matching sample, remaining samples will never be proved.
+                if !state.requested.insert(block_size) {
+                    let requested = &state.requested;
+                    if !state
+                        .pending
+                        .iter()
+                        .any(|sample| requested.contains(&sample.block_size))
+                    {
+                        log::warn!(
+                            "Prover doesn't ask for block sizes of {} remaining samples.",
+                            state.pending.len()
+                        );
+                        self.finish_bench(&state);
+                    }
+                }
+                return Ok(None);
+            }
+        };
+
+        state.requested.clear();
+        state.current = Some(JobTracker::start(
+            sample.block_id,
+            sample.job_id,
+            sample.block_size,
+        ));
+        Ok(Some((sample.block_id, sample.job_id)))
+    }
+
+    fn working_on(&self, _job_id: i32) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn prover_data(&self, block: i64) -> Result<ProverData, anyhow::Error> {
+        let block_path = self.bench_dir.join(format!("block-{}.json", block));
+        let data = read_json::<ProverData>(&block_path).and_then(|data| {
+            data.ok_or_else(|| anyhow!("Sample block [{}] not found.", block_path.display()))
+        });
+
+        match data {
+            Ok(data) => {
+                if let Some(job) = self.state.lock().unwrap().current.as_mut() {
+                    job.loaded();
+                }
+                Ok(data)
+            }
+            Err(e) => {
+                self.finish_run(JobStatus::Failed, Some(("missing-input", e.to_string())));
+                Err(e)
+            }
+        }
+    }
+
+    fn publish(&self, block: i64, proof: EncodedProofPlonk) -> Result<(), anyhow::Error> {
+        let block_size = {
+            let mut state = self.state.lock().unwrap();
+            let job = state
+                .current
+                .as_mut()
+                .ok_or_else(|| anyhow!("Publishing proof for block {} without job.", block))?;
+            job.proved();
+            job.block_size
+        };
+
+        // Verification is part of every real job, so we measure it as well.
+        match verify_proof(&proof, block_size) {
+            Ok(()) => {
+                if let Some(job) = self.state.lock().unwrap().current.as_mut() {
+                    job.verified();
+                }
+                self.finish_run(JobStatus::Success, None);
+            }
+            Err(e) => self.finish_run(
+                JobStatus::InvalidProof,
+                Some(("invalid-proof", e.to_string())),
+            ),
+        }
+        Ok(())
+    }
+
+    fn prover_stopped(&self, _prover_run_id: i32) -> Result<(), anyhow::Error> {
+        self.finish_run(
+            JobStatus::Failed,
+            Some((
+                "prover-error",
+                "Prover stopped before publishing proof.".to_string(),
+            )),
+        );
+        Ok(())
+    }
+
+    fn register_prover(&self, _block_size: usize) -> Result<i32, anyhow::Error> {
+        Ok(1)
+    }
+}
+
+fn summarize(block_size: usize, runs: &[ResultManifest]) -> SizeSummary {
+    let runs = runs
+        .iter()
+        .filter(|run| run.block_size == block_size)
+        .collect::<Vec<_>>();
+    let succeeded = runs
+        .iter()
+        .filter(|run| run.status == JobStatus::Success)
+        .collect::<Vec<_>>();
+
+    let prove = succeeded
+        .iter()
+        .filter_map(|run| run.timings.prove_ms)
+        .collect::<Vec<_>>();
+    let total = succeeded
+        .iter()
+        .filter_map(|run| run.timings.total_ms)
+        .collect::<Vec<_>>();
+
+    SizeSummary {
+        block_size,
+        runs: runs.len(),
+        failed: runs.len() - succeeded.len(),
+        min_prove_ms: prove.iter().min().cloned(),
+        mean_prove_ms: mean(&prove),
+        max_prove_ms: prove.iter().max().cloned(),
+        mean_total_ms: mean(&total),
+    }
+}
+
+fn mean(values: &[u64]) -> Option<u64> {
+    match values.len() {
+        0 => None,
+        len => Some(values.iter().sum::<u64>() / len as u64),
+    }
+}
+
+fn load_samples(dir: &Path) -> anyhow::Result<Vec<BlockInfo>> {
+    let mut samples = vec![];
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| anyhow!("Can't read bench dir [{}]. Error: {}", dir.display(), e))?;
+
+    for entry in entries {
+        let path = entry?.path();
+        match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if name.starts_with("job-info-") && name.ends_with(".json") => (),
+            _ => continue,
+        };
+
+        if let Some(info) = read_json::<BlockInfo>(&path)? {
+            samples.push(info);
+        }
+    }
+    samples.sort_by_key(|sample| (sample.block_size, sample.block_id));
+    Ok(samples)
+}
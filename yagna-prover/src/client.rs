@@ -1,25 +1,85 @@
 use anyhow::anyhow;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{self};
+use structopt::StructOpt;
 
 use zksync_crypto::proof::EncodedProofPlonk;
 use zksync_prover::ApiClient;
 use zksync_prover_utils::prover_data::ProverData;
 
+use crate::manifest::{write_manifest, JobTracker};
+use crate::verify::verify_proof;
+use prover_common::artifact::{read_json, write_json_atomic};
+use prover_common::exit_code::INVALID_PROOF_EXIT_CODE;
+use prover_common::manifest::JobStatus;
+
+/// Directories shared with Yagna Requestor. Defaults are volumes
+/// of ya-zksync-prover image.
+#[derive(StructOpt, Debug, Clone)]
+pub struct ProverDirs {
+    #[structopt(long, env, default_value = "/blocks/", parse(from_os_str))]
+    pub blocks_dir: PathBuf,
+    #[structopt(long, env, default_value = "/proofs/", parse(from_os_str))]
+    pub proofs_dir: PathBuf,
+}
+
 #[derive(Debug, Clone)]
 pub struct YagnaApiClient {
     finish: Arc<AtomicBool>,
+    /// Job returned from `block_to_prove`.
+    job: Arc<Mutex<Option<JobTracker>>>,
+    dirs: ProverDirs,
 }
 
 impl YagnaApiClient {
-    pub fn new(_base_url: &Url, _worker: &str, _req_server_timeout: time::Duration) -> Self {
+    pub fn new(
+        _base_url: &Url,
+        _worker: &str,
+        _req_server_timeout: time::Duration,
+        dirs: ProverDirs,
+    ) -> Self {
         YagnaApiClient {
             finish: Arc::new(AtomicBool::new(false)),
+            job: Arc::new(Mutex::new(None)),
+            dirs,
+        }
+    }
+
+    /// Writes result manifest for current job, so Yagna Requestor knows what happened.
+    fn finish_job(&self, status: JobStatus, error: Option<(&str, String)>) {
+        let job = self.job.lock().unwrap();
+        if let Some(job) = job.as_ref() {
+            write_manifest(&self.dirs.proofs_dir, &job.manifest(status, error))
+                .map_err(|e| log::error!("{}", e))
+                .ok();
+        }
+    }
+
+    /// Panics inside prover would leave Requestor without any information.
+    pub fn install_panic_hook(&self) {
+        let job = self.job.clone();
+        let proofs_dir = self.dirs.proofs_dir.clone();
+        let default_hook = std::panic::take_hook();
+
+        std::panic::set_hook(Box::new(move |info| {
+            if let Ok(job) = job.try_lock() {
+                if let Some(job) = job.as_ref() {
+                    let manifest =
+                        job.manifest(JobStatus::Failed, Some(("panic", info.to_string())));
+                    write_manifest(&proofs_dir, &manifest).ok();
+                }
+            }
+            default_hook(info);
+        }));
+    }
+
+    fn with_job(&self, f: impl FnOnce(&mut JobTracker)) {
+        if let Some(job) = self.job.lock().unwrap().as_mut() {
+            f(job)
         }
     }
 }
@@ -35,25 +95,26 @@ impl ApiClient for YagnaApiClient {
     fn block_to_prove(&self, block_size: usize) -> Result<Option<(i64, i32)>, anyhow::Error> {
         // Download block info from disk.
         // Yagna Requestor should upload json file for us.
-        let job_path = blocks_info_dir().join("job-info.json");
-        let json_file = File::open(&job_path).map_err(|e| {
+        let job_path = self.dirs.blocks_dir.join("job-info.json");
+        let info: BlockInfo = match read_json(&job_path).map_err(|e| {
             anyhow!(
-                "Can't open job info file [{}] to deserialize. Error: {}",
-                &job_path.display(),
-                e
-            )
-        })?;
-        let info: BlockInfo = serde_json::from_reader(json_file).map_err(|e| {
-            anyhow!(
-                "Failed to deserialize info for block of size {}. Error: {}",
+                "Failed to read info for block of size {}. {}",
                 block_size,
                 e
             )
-        })?;
+        })? {
+            Some(info) => info,
+            None => {
+                log::debug!("Job info [{}] is not ready yet.", job_path.display());
+                return Ok(None);
+            }
+        };
 
         // plonk_step_by_step_prover will try with all supported sizes.
         // So we shouldn't confuse him by returning block of different size, then he expected.
         if info.block_size == block_size {
+            *self.job.lock().unwrap() =
+                Some(JobTracker::start(info.block_id, info.job_id, block_size));
             Ok(Some((info.block_id, info.job_id)))
         } else {
             Ok(None)
@@ -75,35 +136,57 @@ impl ApiClient for YagnaApiClient {
         }
 
         // Yagna Requestor will command ExeUnit to download block and place in our directories.
-        let block_path = blocks_info_dir().join(format!("block-{}.json", block));
-
-        let json_file = File::open(&block_path).map_err(|e| {
-            anyhow!(
-                "Can't open block file [{}] to deserialize. Error: {}",
-                &block_path.display(),
-                e
-            )
-        })?;
-        let prover_data: ProverData = serde_json::from_reader(json_file)
-            .map_err(|e| anyhow!("Failed to deserialize block {}. Error: {}", block, e))?;
-
-        Ok(prover_data)
+        let block_path = self.dirs.blocks_dir.join(format!("block-{}.json", block));
+
+        let prover_data = read_json::<ProverData>(&block_path).and_then(|data| {
+            data.ok_or_else(|| anyhow!("Block file [{}] is not ready.", block_path.display()))
+        });
+
+        match prover_data {
+            Ok(prover_data) => {
+                self.with_job(|job| job.loaded());
+                Ok(prover_data)
+            }
+            Err(e) => {
+                self.finish_job(JobStatus::Failed, Some(("missing-input", e.to_string())));
+                Err(e)
+            }
+        }
     }
 
     fn publish(&self, block: i64, proof: EncodedProofPlonk) -> Result<(), anyhow::Error> {
+        self.with_job(|job| job.proved());
+
+        let block_size = self
+            .job
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|job| job.block_size)
+            .ok_or_else(|| anyhow!("Publishing proof for block {} without job.", block))?;
+
+        if let Err(e) = verify_proof(&proof, block_size) {
+            log::error!("Proof for block {} didn't pass verification. {}", block, e);
+            self.finish_job(
+                JobStatus::InvalidProof,
+                Some(("invalid-proof", e.to_string())),
+            );
+            std::process::exit(INVALID_PROOF_EXIT_CODE);
+        }
+        self.with_job(|job| job.verified());
+        log::info!("Proof for block {} verified.", block);
+
         // Serialize proof and save on disk.
         // Yagna Requestor will download it from expected location and send to zksync server.
-        let proof_path = proofs_info_dir().join(format!("proof-{}.json", block));
-        let file = File::create(&proof_path).map_err(|e| {
-            anyhow!(
-                "Can't open proof file [{}]. Error: {}",
-                proof_path.display(),
-                e
-            )
-        })?;
+        let proof_path = self.dirs.proofs_dir.join(format!("proof-{}.json", block));
+        let written = write_json_atomic(&proof_path, &proof);
 
-        serde_json::to_writer(file, &proof)
-            .map_err(|e| anyhow!("Failed to serialize block {}. Error: {}", block, e))?;
+        if let Err(e) = written {
+            self.finish_job(JobStatus::Failed, Some(("write-proof", e.to_string())));
+            return Err(e);
+        }
+        self.with_job(|job| job.written());
+        self.finish_job(JobStatus::Success, None);
 
         // We run only single proof. Yagna Requestor will run VM multiple times.
         // TODO: Implement it better on Requestor side.
@@ -121,11 +204,3 @@ impl ApiClient for YagnaApiClient {
         Ok(32)
     }
 }
-
-pub fn proofs_info_dir() -> PathBuf {
-    PathBuf::from("/proofs/")
-}
-
-pub fn blocks_info_dir() -> PathBuf {
-    PathBuf::from("/blocks/")
-}
@@ -0,0 +1,197 @@
+use anyhow::anyhow;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use prover_common::capabilities::{CapabilityReport, KeyFile, KeyStatus, SizeCapability};
+use zksync_prover_utils::PlonkVerificationKey;
+
+/// Exit code of yagna-prover, when keys for some block sizes are missing or corrupt.
+pub const MISSING_KEYS_EXIT_CODE: i32 = 4;
+
+/// Keys layout configured the same way, as for zksync prover and `download-keys.sh`.
+struct KeysConfig {
+    home: PathBuf,
+    key_dir: Option<String>,
+    tree_dir: String,
+    block_sizes: Vec<usize>,
+    setup_powers: HashMap<usize, u32>,
+    digests: HashMap<String, String>,
+}
+
+impl KeysConfig {
+    fn from_env(verify_digests: bool) -> anyhow::Result<KeysConfig> {
+        let block_sizes = parse_list::<usize>("BLOCK_CHUNK_SIZES")?
+            .ok_or_else(|| anyhow!("BLOCK_CHUNK_SIZES not set."))?;
+        let supported = parse_list::<usize>("SUPPORTED_BLOCK_CHUNKS_SIZES")?.unwrap_or_default();
+        let powers =
+            parse_list::<u32>("SUPPORTED_BLOCK_CHUNKS_SIZES_SETUP_POWERS")?.unwrap_or_default();
+
+        let digests = match std::env::var("KEY_DIGESTS") {
+            Ok(path) if verify_digests => read_digests(Path::new(&path))?,
+            _ => HashMap::new(),
+        };
+
+        Ok(KeysConfig {
+            home: PathBuf::from(std::env::var("ZKSYNC_HOME").unwrap_or_else(|_| "/".to_string())),
+            key_dir: std::env::var("KEY_DIR").ok(),
+            tree_dir: format!(
+                "account-{}_balance-{}",
+                std::env::var("ACCOUNT_TREE_DEPTH").unwrap_or_default(),
+                std::env::var("BALANCE_TREE_DEPTH").unwrap_or_default()
+            ),
+            block_sizes,
+            setup_powers: supported.into_iter().zip(powers).collect(),
+            digests,
+        })
+    }
+
+    fn verification_key_path(&self, block_size: usize) -> PathBuf {
+        self.home
+            .join(self.key_dir.as_deref().unwrap_or_default())
+            .join(&self.tree_dir)
+            .join(format!("verification_block_{}.key", block_size))
+    }
+
+    fn setup_path(&self, power: u32) -> PathBuf {
+        let setup_dir = self.home.join("keys").join("setup");
+        let path = setup_dir.join(format!("setup_2^{}.key", power));
+        // Name left by downloading with url-encoded name.
+        let encoded = setup_dir.join(format!("setup_2%5E{}.key", power));
+        if !path.exists() && encoded.exists() {
+            encoded
+        } else {
+            path
+        }
+    }
+}
+
+/// Checks verification keys and setup powers for all sizes in `BLOCK_CHUNK_SIZES`.
+/// Keys are compared with `KEY_DIGESTS` only with `verify_digests`, because
+/// hashing setup files takes minutes.
+pub fn check_keys(verify_digests: bool) -> anyhow::Result<CapabilityReport> {
+    let config = KeysConfig::from_env(verify_digests)?;
+
+    let sizes = config
+        .block_sizes
+        .iter()
+        .map(|&block_size| check_size(&config, block_size))
+        .collect::<Vec<_>>();
+
+    Ok(CapabilityReport {
+        prover_version: env!("CARGO_PKG_VERSION").to_string(),
+        key_dir: config.key_dir.clone(),
+        ready_sizes: sizes
+            .iter()
+            .filter(|size| size.ready)
+            .map(|size| size.block_size)
+            .collect(),
+        sizes,
+    })
+}
+
+fn check_size(config: &KeysConfig, block_size: usize) -> SizeCapability {
+    let mut verification_key = check_file(config, &config.verification_key_path(block_size));
+    if verification_key.status == KeyStatus::Ok {
+        // File may be complete, but still not usable by prover.
+        if let Err(e) = PlonkVerificationKey::read_verification_key_for_main_circuit(block_size) {
+            verification_key.status = KeyStatus::Unreadable;
+            verification_key.error = Some(e.to_string());
+        }
+    }
+
+    let setup_power = config.setup_powers.get(&block_size).cloned();
+    let setup = setup_power.map(|power| check_file(config, &config.setup_path(power)));
+
+    let ready = verification_key.status == KeyStatus::Ok
+        && setup
+            .as_ref()
+            .map(|setup| setup.status == KeyStatus::Ok)
+            .unwrap_or(false);
+
+    SizeCapability {
+        block_size,
+        setup_power,
+        verification_key,
+        setup,
+        ready,
+    }
+}
+
+fn check_file(config: &KeysConfig, path: &Path) -> KeyFile {
+    let expected_sha256 = path
+        .file_name()
+        .and_then(|name| config.digests.get(name.to_string_lossy().as_ref()))
+        .cloned();
+
+    let mut key = KeyFile {
+        path: path.display().to_string(),
+        status: KeyStatus::Ok,
+        sha256: None,
+        expected_sha256,
+        error: None,
+    };
+
+    if !path.exists() {
+        key.status = KeyStatus::Missing;
+        return key;
+    }
+
+    // Hashing large setup files takes time, so we do it only if there is anything to compare.
+    if let Some(expected) = key.expected_sha256.clone() {
+        match sha256(path) {
+            Ok(digest) => {
+                if digest != expected {
+                    key.status = KeyStatus::DigestMismatch;
+                }
+                key.sha256 = Some(digest);
+            }
+            Err(e) => {
+                key.status = KeyStatus::Unreadable;
+                key.error = Some(e.to_string());
+            }
+        }
+    }
+    key
+}
+
+fn sha256(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.result()))
+}
+
+/// Reads digests in `sha256sum` output format. Files are identified by name.
+fn read_digests(path: &Path) -> anyhow::Result<HashMap<String, String>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Can't read key digests [{}]. Error: {}", path.display(), e))?;
+
+    Ok(content
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let digest = parts.next()?;
+            let file = Path::new(parts.next()?.trim_start_matches('*'));
+            let name = file.file_name()?.to_string_lossy().to_string();
+            Some((name, digest.to_lowercase()))
+        })
+        .collect())
+}
+
+pub fn parse_list<T: std::str::FromStr>(var: &str) -> anyhow::Result<Option<Vec<T>>> {
+    match std::env::var(var) {
+        Ok(value) => value
+            .split(',')
+            .filter(|item| !item.trim().is_empty())
+            .map(|item| {
+                item.trim()
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid value '{}' in {}.", item, var))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map(Some),
+        Err(_) => Ok(None),
+    }
+}
@@ -1,24 +1,197 @@
 use zksync_prover::cli_utils::{main_prover_internal, Opt};
 use zksync_prover::plonk_step_by_step_prover::PlonkStepByStepProver;
-use zksync_utils::parse_env;
 
+mod bench;
 mod client;
-use crate::client::YagnaApiClient;
+mod keys;
+mod manifest;
+mod network;
+mod samples;
+mod verify;
+use crate::bench::{BenchApiClient, BenchArgs};
+use crate::client::{ProverDirs, YagnaApiClient};
+use crate::keys::{check_keys, parse_list, MISSING_KEYS_EXIT_CODE};
+use crate::network::{read_network_config, NetworkApiClient};
+use crate::samples::generate_samples;
+use prover_common::artifact::write_json_atomic;
+use prover_common::capabilities::{CapabilityReport, CAPABILITIES_FILE};
 
+use prover_common::network::NETWORK_CONFIG_FILE;
+use reqwest::Url;
+use std::str::FromStr;
 use std::time::Duration;
 use structopt::StructOpt;
 
+enum Mode {
+    /// Exchange blocks and proofs with Yagna Requestor through directories.
+    File,
+    /// Communicate with zksync prover server directly.
+    Network,
+    /// Only check keys and print capability report.
+    CheckKeys,
+    /// Prove sample blocks and print timings.
+    Bench,
+    /// Generate sample blocks for bench mode.
+    Samples,
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "file" => Mode::File,
+            "network" => Mode::Network,
+            "check-keys" => Mode::CheckKeys,
+            "bench" => Mode::Bench,
+            "samples" => Mode::Samples,
+            _ => anyhow::bail!(
+                "Unknown mode '{}'. Use: file, network, check-keys, bench or samples.",
+                s
+            ),
+        })
+    }
+}
+
+#[derive(StructOpt)]
+struct Args {
+    #[structopt(flatten)]
+    opt: Opt,
+    #[structopt(long, env = "PROVER_MODE", default_value = "file")]
+    mode: Mode,
+    /// Used only in network mode.
+    #[structopt(
+        long,
+        env = "PROVER_SERVER_URL",
+        default_value = "http://127.0.0.1:8088"
+    )]
+    server_url: Url,
+    /// Request timeout in seconds. Used only in network mode.
+    #[structopt(long, env = "REQ_SERVER_TIMEOUT", default_value = "10")]
+    req_server_timeout: u64,
+    #[structopt(flatten)]
+    dirs: ProverDirs,
+    /// Used only in bench and samples mode.
+    #[structopt(flatten)]
+    bench: BenchArgs,
+}
+
 fn main() {
-    let opt = Opt::from_args();
-    let worker_name = opt.worker_name;
-
-    // Doesn't matter. We don't communicate with any server.
-    let server_api_url = parse_env("PROVER_SERVER_URL");
-    let request_timout = Duration::from_secs(parse_env::<u64>("REQ_SERVER_TIMEOUT"));
-    let api_client = YagnaApiClient::new(&server_api_url, &worker_name, request_timout);
-
-    main_prover_internal::<YagnaApiClient, PlonkStepByStepProver<YagnaApiClient>>(
-        &worker_name,
-        api_client,
-    );
+    let args = Args::from_args();
+    let worker_name = args.opt.worker_name;
+    let request_timeout = Duration::from_secs(args.req_server_timeout);
+
+    // Samples are generated at image build time and don't need keys.
+    if let Mode::Samples = args.mode {
+        let generated = parse_list::<usize>("BLOCK_CHUNK_SIZES")
+            .and_then(|sizes| generate_samples(&args.bench.bench_dir, &sizes.unwrap_or_default()));
+        if let Err(e) = generated {
+            eprintln!("Failed to generate sample blocks. {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Digests are verified once, when Requestor checks Provider. Proving
+    // runs only check, that keys are in place.
+    let report = startup_check(&args.dirs, matches!(args.mode, Mode::CheckKeys));
+
+    match args.mode {
+        Mode::CheckKeys => {
+            if let Ok(json) = serde_json::to_string(&report) {
+                println!("{}", json);
+            }
+            if !report.all_ready() {
+                std::process::exit(MISSING_KEYS_EXIT_CODE);
+            }
+        }
+        _ if report.ready_sizes.is_empty() => {
+            eprintln!("No keys available for any block size.");
+            std::process::exit(MISSING_KEYS_EXIT_CODE);
+        }
+        Mode::File => {
+            // Doesn't matter. We don't communicate with any server.
+            let api_client =
+                YagnaApiClient::new(&args.server_url, &worker_name, request_timeout, args.dirs);
+            api_client.install_panic_hook();
+
+            main_prover_internal::<YagnaApiClient, PlonkStepByStepProver<YagnaApiClient>>(
+                &worker_name,
+                api_client,
+            );
+        }
+        Mode::Network => {
+            let config_path = args.dirs.blocks_dir.join(NETWORK_CONFIG_FILE);
+            let api_client = read_network_config(&config_path)
+                .and_then(|config| {
+                    NetworkApiClient::new(
+                        &args.server_url,
+                        &worker_name,
+                        request_timeout,
+                        config,
+                        args.dirs.proofs_dir.clone(),
+                    )
+                })
+                .unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                });
+
+            main_prover_internal::<NetworkApiClient, PlonkStepByStepProver<NetworkApiClient>>(
+                &worker_name,
+                api_client,
+            );
+        }
+        Mode::Bench => {
+            let api_client =
+                BenchApiClient::new(&args.bench, &args.dirs.proofs_dir, &report.ready_sizes)
+                    .unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    });
+
+            main_prover_internal::<BenchApiClient, PlonkStepByStepProver<BenchApiClient>>(
+                &worker_name,
+                api_client,
+            );
+        }
+        // Handled before keys check.
+        Mode::Samples => (),
+    }
+}
+
+/// Finds missing keys early, instead of inside prover. Prover is limited
+/// to block sizes, that have all keys in place. Logger isn't initialized yet,
+/// so we print directly to stderr.
+fn startup_check(dirs: &ProverDirs, verify_digests: bool) -> CapabilityReport {
+    let report = match check_keys(verify_digests) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Keys configuration is invalid. {}", e);
+            std::process::exit(MISSING_KEYS_EXIT_CODE);
+        }
+    };
+
+    // Yagna Requestor collects report from proofs directory.
+    write_json_atomic(&dirs.proofs_dir.join(CAPABILITIES_FILE), &report)
+        .map_err(|e| eprintln!("Failed to write capability report. {}", e))
+        .ok();
+
+    for size in report.sizes.iter().filter(|size| !size.ready) {
+        eprintln!(
+            "Block size {} is not supported. Verification key: {:?}, setup: {:?}.",
+            size.block_size,
+            size.verification_key.status,
+            size.setup.as_ref().map(|setup| setup.status)
+        );
+    }
+
+    let sizes = report
+        .ready_sizes
+        .iter()
+        .map(|size| size.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    std::env::set_var("BLOCK_CHUNK_SIZES", sizes);
+    report
 }
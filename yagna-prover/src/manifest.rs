@@ -0,0 +1,88 @@
+use std::path::Path;
+use std::time::Instant;
+
+use prover_common::artifact::write_json_atomic;
+use prover_common::manifest::{manifest_path, JobStatus, PhaseTimings, ResultManifest};
+
+/// Collects information about job in progress.
+#[derive(Debug)]
+pub struct JobTracker {
+    pub block_id: i64,
+    pub job_id: i32,
+    pub block_size: usize,
+    started: Instant,
+    phase_started: Instant,
+    timings: PhaseTimings,
+}
+
+impl JobTracker {
+    pub fn start(block_id: i64, job_id: i32, block_size: usize) -> JobTracker {
+        let now = Instant::now();
+        JobTracker {
+            block_id,
+            job_id,
+            block_size,
+            started: now,
+            phase_started: now,
+            timings: PhaseTimings::default(),
+        }
+    }
+
+    /// Finishes current phase and returns it's duration in milliseconds.
+    fn lap(&mut self) -> Option<u64> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.phase_started).as_millis() as u64;
+        self.phase_started = now;
+        Some(elapsed)
+    }
+
+    pub fn loaded(&mut self) {
+        self.timings.load_ms = self.lap();
+    }
+
+    pub fn proved(&mut self) {
+        self.timings.prove_ms = self.lap();
+    }
+
+    pub fn verified(&mut self) {
+        self.timings.verify_ms = self.lap();
+    }
+
+    pub fn written(&mut self) {
+        self.timings.write_ms = self.lap();
+    }
+
+    pub fn manifest(&self, status: JobStatus, error: Option<(&str, String)>) -> ResultManifest {
+        let mut timings = self.timings.clone();
+        timings.total_ms = Some(self.started.elapsed().as_millis() as u64);
+
+        ResultManifest {
+            block_id: self.block_id,
+            job_id: self.job_id,
+            block_size: self.block_size,
+            status,
+            error_class: error.as_ref().map(|(class, _)| class.to_string()),
+            error: error.map(|(_, message)| message),
+            timings,
+            peak_memory_kib: peak_memory_kib(),
+            prover_version: env!("CARGO_PKG_VERSION").to_string(),
+            key_dir: std::env::var("KEY_DIR").ok(),
+        }
+    }
+}
+
+pub fn write_manifest(proofs_dir: &Path, manifest: &ResultManifest) -> anyhow::Result<()> {
+    write_json_atomic(&manifest_path(proofs_dir, manifest.block_id), manifest)
+}
+
+/// Peak resident memory of this process. Available only on Linux.
+fn peak_memory_kib() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find(|line| line.starts_with("VmHWM:"))?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
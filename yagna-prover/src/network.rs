@@ -0,0 +1,356 @@
+use anyhow::{anyhow, bail};
+use reqwest::blocking::{Client, Response};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Certificate, Identity, Method, Proxy, Url};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time;
+
+use crate::manifest::{write_manifest, JobTracker};
+use crate::verify::verify_proof;
+use prover_common::artifact::read_json;
+use prover_common::auth::Auth;
+use prover_common::exit_code::INVALID_PROOF_EXIT_CODE;
+use prover_common::manifest::JobStatus;
+use prover_common::network::{split_pem_bundle, NetworkConfig};
+use prover_common::retry::{
+    is_retryable_status, retry_blocking, RetryPolicy, DUPLICATE_PROOF_RESPONSE,
+};
+use zksync_crypto::proof::EncodedProofPlonk;
+use zksync_prover::ApiClient;
+use zksync_prover_utils::api::{BlockToProveRes, ProverReq, PublishReq, WorkingOnReq};
+use zksync_prover_utils::prover_data::ProverData;
+
+/// Communicates with zksync prover server directly. Can be used only
+/// on Providers, that allow outbound network traffic. Connection settings
+/// come from Requestor in `NetworkConfig`.
+#[derive(Debug, Clone)]
+pub struct NetworkApiClient {
+    base_url: Url,
+    worker: String,
+    auth: Auth,
+    default_policy: RetryPolicy,
+    policies: HashMap<String, RetryPolicy>,
+    http_client: Client,
+    /// Job returned from `block_to_prove`.
+    job: Arc<Mutex<Option<JobTracker>>>,
+    proofs_dir: PathBuf,
+}
+
+impl NetworkApiClient {
+    pub fn new(
+        base_url: &Url,
+        worker: &str,
+        req_server_timeout: time::Duration,
+        config: NetworkConfig,
+        proofs_dir: PathBuf,
+    ) -> anyhow::Result<Self> {
+        if worker.is_empty() {
+            bail!("Worker name cannot be empty");
+        }
+
+        let default_policy = match &config.retry_policy {
+            Some(policy) => RetryPolicy::from_str(policy)?,
+            None => RetryPolicy::default(),
+        };
+        let policies = config
+            .endpoint_retry_policies
+            .iter()
+            .map(|(endpoint, policy)| Ok((endpoint.clone(), RetryPolicy::from_str(policy)?)))
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+
+        let auth = Auth::new(config.auth_token.clone(), config.hmac_secret.clone());
+
+        Ok(NetworkApiClient {
+            base_url: base_url.clone(),
+            worker: worker.to_string(),
+            auth,
+            default_policy,
+            policies,
+            http_client: http_client(worker, req_server_timeout, &config)?,
+            job: Arc::new(Mutex::new(None)),
+            proofs_dir,
+        })
+    }
+
+    /// Writes result manifest for current job. Requestor doesn't see, what
+    /// prover sends to server, so this is the only trace of each job.
+    fn finish_job(&self, status: JobStatus, error: Option<(&str, String)>) {
+        if let Some(job) = self.job.lock().unwrap().take() {
+            write_manifest(&self.proofs_dir, &job.manifest(status, error))
+                .map_err(|e| log::error!("{}", e))
+                .ok();
+        }
+    }
+
+    fn with_job(&self, f: impl FnOnce(&mut JobTracker)) {
+        if let Some(job) = self.job.lock().unwrap().as_mut() {
+            f(job)
+        }
+    }
+
+    fn send<T: Serialize + ?Sized>(
+        &self,
+        endpoint: &str,
+        method: Method,
+        body: &T,
+    ) -> Result<Response, backoff::Error<anyhow::Error>> {
+        let path = format!("/{}", endpoint);
+        let url = self
+            .base_url
+            .join(&path)
+            .map_err(|e| permanent(anyhow!("invalid {} url: {}", endpoint, e)))?;
+        let body = serde_json::to_vec(body)
+            .map_err(|e| permanent(anyhow!("failed to serialize {} request: {}", endpoint, e)))?;
+
+        let request = self
+            .http_client
+            .request(method, url)
+            .header(CONTENT_TYPE, "application/json");
+        self.auth
+            .headers(&path, &body)
+            .into_iter()
+            .fold(request, |request, (name, value)| {
+                request.header(name, value)
+            })
+            .body(body)
+            .send()
+            .map_err(|e| {
+                let error = anyhow!("{} request failed: {}", endpoint, e);
+                match e.is_builder() {
+                    true => permanent(error),
+                    false => transient(error),
+                }
+            })
+    }
+
+    fn with_retries<T>(
+        &self,
+        endpoint: &str,
+        operation: impl FnMut() -> Result<T, backoff::Error<anyhow::Error>>,
+    ) -> Result<T, anyhow::Error> {
+        let policy = self.policies.get(endpoint).unwrap_or(&self.default_policy);
+        retry_blocking(policy, &format!("{} request", endpoint), operation)
+    }
+}
+
+fn http_client(
+    worker: &str,
+    req_server_timeout: time::Duration,
+    config: &NetworkConfig,
+) -> anyhow::Result<Client> {
+    let user_agent = config.user_agent.clone().unwrap_or_else(|| {
+        format!(
+            "{}/{} ({})",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            worker
+        )
+    });
+    let mut builder = reqwest::blocking::ClientBuilder::new()
+        .user_agent(user_agent)
+        .timeout(req_server_timeout);
+
+    if let Some(bundle) = &config.ca_bundle {
+        for pem in split_pem_bundle(bundle) {
+            let cert = Certificate::from_pem(pem.as_bytes())
+                .map_err(|e| anyhow!("Invalid certificate in CA bundle. Error: {}", e))?;
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    if let Some(identity) = &config.client_identity {
+        let der = hex::decode(identity)
+            .map_err(|e| anyhow!("Client identity isn't valid hex. Error: {}", e))?;
+        let identity = Identity::from_pkcs12_der(&der, &config.client_identity_password)
+            .map_err(|e| anyhow!("Invalid client identity. Error: {}", e))?;
+        builder = builder.identity(identity);
+    }
+
+    if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(Proxy::all(proxy.as_str())?);
+    }
+
+    builder
+        .build()
+        .map_err(|e| anyhow!("Failed to create request client. Error: {}", e))
+}
+
+fn read_response(
+    endpoint: &str,
+    res: Response,
+) -> Result<(reqwest::StatusCode, String), backoff::Error<anyhow::Error>> {
+    let status = res.status();
+    let text = res
+        .text()
+        .map_err(|e| transient(anyhow!("failed to read {} response: {}", endpoint, e)))?;
+    Ok((status, text))
+}
+
+fn check_status(
+    endpoint: &str,
+    (status, text): (reqwest::StatusCode, String),
+) -> Result<String, backoff::Error<anyhow::Error>> {
+    if status.is_success() {
+        return Ok(text);
+    }
+
+    let error = anyhow!(
+        "{} request failed with status: {} and message: {}",
+        endpoint,
+        status,
+        text
+    );
+    match is_retryable_status(status.as_u16()) {
+        true => Err(transient(error)),
+        false => Err(permanent(error)),
+    }
+}
+
+fn transient(e: anyhow::Error) -> backoff::Error<anyhow::Error> {
+    backoff::Error::Transient(e)
+}
+
+fn permanent(e: anyhow::Error) -> backoff::Error<anyhow::Error> {
+    backoff::Error::Permanent(e)
+}
+
+impl ApiClient for NetworkApiClient {
+    fn block_to_prove(&self, block_size: usize) -> Result<Option<(i64, i32)>, anyhow::Error> {
+        self.with_retries("block_to_prove", || {
+            let req = ProverReq {
+                name: self.worker.clone(),
+                block_size,
+            };
+            let res = self.send("block_to_prove", Method::GET, &req)?;
+            let text = check_status("block_to_prove", read_response("block_to_prove", res)?)?;
+            let res: BlockToProveRes = serde_json::from_str(&text).map_err(|e| {
+                permanent(anyhow!("failed to parse block_to_prove response: {}", e))
+            })?;
+            if res.block != 0 {
+                return Ok(Some((res.block, res.prover_run_id)));
+            }
+            Ok(None)
+        })
+        .map(|job| {
+            if let Some((block_id, job_id)) = job {
+                *self.job.lock().unwrap() = Some(JobTracker::start(block_id, job_id, block_size));
+            }
+            job
+        })
+    }
+
+    fn working_on(&self, job_id: i32) -> Result<(), anyhow::Error> {
+        // Heartbeat is sent periodically by prover, so we don't retry it.
+        let req = WorkingOnReq {
+            prover_run_id: job_id,
+        };
+        let result = self
+            .send("working_on", Method::POST, &req)
+            .and_then(|res| read_response("working_on", res))
+            .and_then(|response| check_status("working_on", response));
+        match result {
+            Ok(_) => Ok(()),
+            Err(backoff::Error::Permanent(e)) | Err(backoff::Error::Transient(e)) => Err(e),
+        }
+    }
+
+    fn prover_data(&self, block: i64) -> Result<ProverData, anyhow::Error> {
+        let prover_data = self.with_retries("prover_data", || {
+            let res = self.send("prover_data", Method::GET, &block)?;
+            let text = check_status("prover_data", read_response("prover_data", res)?)?;
+            let res: Option<ProverData> = serde_json::from_str(&text)
+                .map_err(|e| permanent(anyhow!("failed to parse prover_data response: {}", e)))?;
+            res.ok_or_else(|| transient(anyhow!("ProverData for block {} is not ready yet", block)))
+        });
+
+        match &prover_data {
+            Ok(_) => self.with_job(|job| job.loaded()),
+            Err(e) => self.finish_job(JobStatus::Failed, Some(("missing-input", e.to_string()))),
+        }
+        prover_data
+    }
+
+    fn publish(&self, block: i64, proof: EncodedProofPlonk) -> Result<(), anyhow::Error> {
+        self.with_job(|job| job.proved());
+
+        let block_size = self
+            .job
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|job| job.block_size)
+            .ok_or_else(|| anyhow!("Publishing proof for block {} without job.", block))?;
+
+        // Don't publish proofs broken by faulty hardware. Requestor learns
+        // about them from exit code.
+        if let Err(e) = verify_proof(&proof, block_size) {
+            log::error!("Proof for block {} didn't pass verification. {}", block, e);
+            self.finish_job(
+                JobStatus::InvalidProof,
+                Some(("invalid-proof", e.to_string())),
+            );
+            std::process::exit(INVALID_PROOF_EXIT_CODE);
+        }
+        self.with_job(|job| job.verified());
+        log::info!("Proof for block {} verified.", block);
+
+        let published = self.with_retries("publish", || {
+            let req = PublishReq {
+                block: block as u32,
+                proof: proof.clone(),
+            };
+            let res = self.send("publish", Method::POST, &req)?;
+            let (status, text) = read_response("publish", res)?;
+            if !status.is_success() && text == DUPLICATE_PROOF_RESPONSE {
+                log::warn!("proof for block {} already exists", block);
+                return Ok(());
+            }
+            check_status("publish", (status, text))?;
+            Ok(())
+        });
+
+        match &published {
+            Ok(()) => {
+                self.with_job(|job| job.written());
+                self.finish_job(JobStatus::Success, None);
+            }
+            Err(e) => self.finish_job(JobStatus::Failed, Some(("publish", e.to_string()))),
+        }
+        published
+    }
+
+    fn prover_stopped(&self, prover_run_id: i32) -> Result<(), anyhow::Error> {
+        self.with_retries("stopped", || {
+            let res = self.send("stopped", Method::POST, &prover_run_id)?;
+            check_status("stopped", read_response("stopped", res)?)?;
+            Ok(())
+        })
+    }
+
+    fn register_prover(&self, block_size: usize) -> Result<i32, anyhow::Error> {
+        log::debug!("Registering prover... Block size: {}", block_size);
+        self.with_retries("register", || {
+            let req = ProverReq {
+                name: self.worker.clone(),
+                block_size,
+            };
+            let res = self.send("register", Method::POST, &req)?;
+            let text = check_status("register", read_response("register", res)?)?;
+            i32::from_str(&text)
+                .map_err(|e| permanent(anyhow!("failed to parse register prover id: {}", e)))
+        })
+    }
+}
+
+/// Reads config left by Requestor. Without it prover connects with defaults.
+pub fn read_network_config(path: &std::path::Path) -> anyhow::Result<NetworkConfig> {
+    match read_json(path)? {
+        Some(config) => Ok(config),
+        None if path.exists() => bail!("Network config [{}] is incomplete.", path.display()),
+        None => Ok(NetworkConfig::default()),
+    }
+}
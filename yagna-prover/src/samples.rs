@@ -0,0 +1,77 @@
+use anyhow::anyhow;
+use std::path::Path;
+
+use zksync_circuit::witness::utils::WitnessBuilder;
+use zksync_crypto::circuit::account::CircuitAccount;
+use zksync_crypto::circuit::CircuitAccountTree;
+use zksync_crypto::ff::PrimeField;
+use zksync_crypto::params::account_tree_depth;
+use zksync_crypto::Fr;
+use zksync_prover_utils::prover_data::ProverData;
+use zksync_types::{Account, AccountId, Address, BlockNumber};
+
+use crate::client::BlockInfo;
+use prover_common::artifact::write_json_atomic;
+
+/// Generated blocks have tree with this single account, that collects fees.
+const FEE_ACCOUNT_ID: AccountId = 0;
+
+/// Writes block of every size in `block_sizes` to `dir` in layout expected by
+/// bench mode. Blocks contain only noop operations, but proving them takes
+/// the same time, as proving full blocks of the same size.
+pub fn generate_samples(dir: &Path, block_sizes: &[usize]) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| anyhow!("Can't create [{}]. Error: {}", dir.display(), e))?;
+
+    for (idx, &block_size) in block_sizes.iter().enumerate() {
+        let info = BlockInfo {
+            block_id: idx as i64 + 1,
+            job_id: idx as i32 + 1,
+            block_size,
+        };
+        let block = empty_block(info.block_id as BlockNumber, block_size)?;
+
+        write_json_atomic(&dir.join(format!("block-{}.json", info.block_id)), &block)?;
+        write_json_atomic(&dir.join(format!("job-info-{}.json", info.job_id)), &info)?;
+        eprintln!(
+            "Generated sample block {} of size {}.",
+            info.block_id, block_size
+        );
+    }
+    Ok(())
+}
+
+fn empty_block(block_number: BlockNumber, block_size: usize) -> anyhow::Result<ProverData> {
+    let mut tree = CircuitAccountTree::new(account_tree_depth());
+    let fee_account = Account::default_with_address(&Address::zero());
+    tree.insert(FEE_ACCOUNT_ID, CircuitAccount::from(fee_account));
+
+    let mut witness = WitnessBuilder::new(&mut tree, FEE_ACCOUNT_ID, block_number);
+    witness.extend_pubdata_with_noops(block_size);
+    witness.collect_fees(&[]);
+    witness.calculate_pubdata_commitment();
+
+    let missing = |what: &str| anyhow!("Witness of block {} has no {}.", block_number, what);
+    Ok(ProverData {
+        public_data_commitment: witness
+            .pubdata_commitment
+            .ok_or_else(|| missing("pubdata commitment"))?,
+        old_root: witness.initial_root_hash,
+        initial_used_subtree_root: witness.initial_used_subtree_root_hash,
+        new_root: witness
+            .root_after_fees
+            .ok_or_else(|| missing("root after fees"))?,
+        validator_address: Fr::from_str(&FEE_ACCOUNT_ID.to_string())
+            .ok_or_else(|| missing("validator address"))?,
+        operations: witness.operations,
+        validator_balances: witness
+            .fee_account_balances
+            .ok_or_else(|| missing("fee account balances"))?,
+        validator_audit_path: witness
+            .fee_account_audit_path
+            .ok_or_else(|| missing("fee account audit path"))?,
+        validator_account: witness
+            .fee_account_witness
+            .ok_or_else(|| missing("fee account witness"))?,
+    })
+}
@@ -0,0 +1,141 @@
+use anyhow::{anyhow, bail};
+
+use zksync_crypto::bellman::pairing::bn256::{Fq, G1Affine};
+use zksync_crypto::bellman::pairing::ff::{PrimeField, PrimeFieldRepr};
+use zksync_crypto::bellman::pairing::CurveAffine;
+use zksync_crypto::bellman::plonk::better_cs::cs::PlonkCsWidth4WithNextStepParams;
+use zksync_crypto::bellman::plonk::better_cs::keys::Proof;
+use zksync_crypto::bellman::plonk::better_cs::verifier::verify;
+use zksync_crypto::bellman::plonk::commitments::transcript::keccak_transcript::RollingKeccakTranscript;
+use zksync_crypto::proof::EncodedProofPlonk;
+use zksync_crypto::{Engine, Fr};
+use zksync_prover_utils::PlonkVerificationKey;
+use zksync_types::U256;
+
+const STATE_WIDTH: usize = 4;
+
+/// Verifies proof against verification key for block size from `KEY_DIR`.
+/// Catches proofs broken by faulty hardware before they leave Provider.
+pub fn verify_proof(proof: &EncodedProofPlonk, block_size: usize) -> anyhow::Result<()> {
+    let vk =
+        PlonkVerificationKey::read_verification_key_for_main_circuit(block_size).map_err(|e| {
+            anyhow!(
+                "Can't read verification key for block size {}. Error: {}",
+                block_size,
+                e
+            )
+        })?;
+
+    let mut decoded = decode_proof(proof)?;
+    // Encoded proof doesn't contain domain size, so we take it from key.
+    decoded.n = vk.0.n;
+    decoded.num_inputs = vk.0.num_inputs;
+
+    let valid = verify::<_, _, RollingKeccakTranscript<Fr>>(&decoded, &vk.0, None)
+        .map_err(|e| anyhow!("Proof verification failed. Error: {:?}", e))?;
+    if !valid {
+        bail!("Proof is invalid for block size {}.", block_size);
+    }
+    Ok(())
+}
+
+/// Reverses `serialize_proof` from zksync prover utils.
+fn decode_proof(
+    encoded: &EncodedProofPlonk,
+) -> anyhow::Result<Proof<Engine, PlonkCsWidth4WithNextStepParams>> {
+    let mut proof = Proof::<Engine, PlonkCsWidth4WithNextStepParams>::empty();
+
+    let mut reader = Reader {
+        words: encoded.proof.iter().map(to_bytes).collect(),
+        position: 0,
+    };
+
+    proof.input_values = encoded
+        .inputs
+        .iter()
+        .map(|input| read_fr(&to_bytes(input)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    proof.wire_commitments = (0..STATE_WIDTH)
+        .map(|_| reader.g1())
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    proof.grand_product_commitment = reader.g1()?;
+    proof.quotient_poly_commitments = (0..STATE_WIDTH)
+        .map(|_| reader.g1())
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    proof.wire_values_at_z = (0..STATE_WIDTH)
+        .map(|_| reader.fr())
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    proof.wire_values_at_z_omega = vec![reader.fr()?];
+
+    proof.grand_product_at_z_omega = reader.fr()?;
+    proof.quotient_polynomial_at_z = reader.fr()?;
+    proof.linearization_polynomial_at_z = reader.fr()?;
+
+    proof.permutation_polynomials_at_z = (0..STATE_WIDTH - 1)
+        .map(|_| reader.fr())
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    proof.opening_at_z_proof = reader.g1()?;
+    proof.opening_at_z_omega_proof = reader.g1()?;
+
+    if reader.position != reader.words.len() {
+        bail!(
+            "Encoded proof has {} elements, expected {}.",
+            reader.words.len(),
+            reader.position
+        );
+    }
+    Ok(proof)
+}
+
+struct Reader {
+    words: Vec<[u8; 32]>,
+    position: usize,
+}
+
+impl Reader {
+    fn next(&mut self) -> anyhow::Result<[u8; 32]> {
+        let word = self
+            .words
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| anyhow!("Encoded proof is too short."))?;
+        self.position += 1;
+        Ok(word)
+    }
+
+    fn fr(&mut self) -> anyhow::Result<Fr> {
+        read_fr(&self.next()?)
+    }
+
+    fn g1(&mut self) -> anyhow::Result<G1Affine> {
+        let x = self.next()?;
+        let y = self.next()?;
+        // Point at infinity is serialized as (0, 0).
+        if x.iter().chain(y.iter()).all(|byte| *byte == 0) {
+            return Ok(G1Affine::zero());
+        }
+        G1Affine::from_xy_checked(read_fq(&x)?, read_fq(&y)?)
+            .map_err(|e| anyhow!("Invalid curve point in proof. Error: {:?}", e))
+    }
+}
+
+fn to_bytes(value: &U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
+
+fn read_fr(bytes: &[u8; 32]) -> anyhow::Result<Fr> {
+    let mut repr = <Fr as PrimeField>::Repr::default();
+    repr.read_be(&bytes[..])?;
+    Fr::from_repr(repr).map_err(|e| anyhow!("Invalid field element in proof. Error: {:?}", e))
+}
+
+fn read_fq(bytes: &[u8; 32]) -> anyhow::Result<Fq> {
+    let mut repr = <Fq as PrimeField>::Repr::default();
+    repr.read_be(&bytes[..])?;
+    Fq::from_repr(repr).map_err(|e| anyhow!("Invalid field element in proof. Error: {:?}", e))
+}